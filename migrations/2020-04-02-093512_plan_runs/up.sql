@@ -0,0 +1,28 @@
+-- plan_runs --
+
+create table plan_runs
+(
+    id               uuid        default uuid_generate_v4() not null,
+    source           int                                    not null,
+    phase            int                                    not null,
+    outcome          int                                    not null,
+    error            text,
+    import_intent_id uuid,
+    scrape_intent_id uuid,
+    started_at       timestamptz default now()              not null,
+    finished_at      timestamptz,
+    created_at       timestamptz default now()              not null,
+    updated_at       timestamptz default now()              not null
+);
+
+create unique index plan_runs_id_uindex
+    on plan_runs (id);
+
+create index plan_runs_source_started_at_index
+    on plan_runs (source, started_at desc);
+
+alter table plan_runs
+    add constraint plan_runs_pk
+        primary key (id);
+
+SELECT diesel_manage_updated_at('plan_runs');
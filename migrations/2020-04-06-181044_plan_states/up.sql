@@ -0,0 +1,18 @@
+-- plan_states --
+
+create table plan_states
+(
+    source               int                       not null,
+    consecutive_failures int         default 0     not null,
+    degraded             boolean     default false not null,
+    last_error           text,
+    degraded_at          timestamptz,
+    created_at           timestamptz default now() not null,
+    updated_at           timestamptz default now() not null
+);
+
+alter table plan_states
+    add constraint plan_states_pk
+        primary key (source);
+
+SELECT diesel_manage_updated_at('plan_states');
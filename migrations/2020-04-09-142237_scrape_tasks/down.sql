@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+drop table scrape_jobs;
+drop table scrape_tasks;
+drop table scrape_schedules;
@@ -0,0 +1,77 @@
+-- scrape_schedules --
+
+-- Anime titles known to the scheduler. Rows are added when result of an index import
+-- is applied and are handed out to scrapers once `next_update_at` is reached.
+create table scrape_schedules
+(
+    id             uuid        default uuid_generate_v4() not null,
+    source         int                                    not null,
+    anime_id       int                                    not null,
+    state          int         default 1                  not null,
+    next_update_at timestamptz default now()              not null,
+    update_count   int         default 0                  not null,
+    created_at     timestamptz default now()              not null,
+    updated_at     timestamptz default now()              not null
+);
+
+create unique index scrape_schedules_id_uindex
+    on scrape_schedules (id);
+
+create unique index scrape_schedules_source_anime_id_uindex
+    on scrape_schedules (source, anime_id);
+
+create index scrape_schedules_next_update_at_index
+    on scrape_schedules (source, state, next_update_at);
+
+alter table scrape_schedules
+    add constraint scrape_schedules_pk
+        primary key (id);
+
+SELECT diesel_manage_updated_at('scrape_schedules');
+
+-- scrape_tasks --
+
+create table scrape_tasks
+(
+    id         uuid        default uuid_generate_v4() not null,
+    source     int                                    not null,
+    finished   boolean     default false              not null,
+    created_at timestamptz default now()              not null,
+    updated_at timestamptz default now()              not null
+);
+
+create unique index scrape_tasks_id_uindex
+    on scrape_tasks (id);
+
+alter table scrape_tasks
+    add constraint scrape_tasks_pk
+        primary key (id);
+
+SELECT diesel_manage_updated_at('scrape_tasks');
+
+-- scrape_jobs --
+
+create table scrape_jobs
+(
+    id          uuid        default uuid_generate_v4() not null,
+    task_id     uuid                                   not null
+        constraint scrape_jobs_scrape_tasks_id_fk
+            references scrape_tasks
+            on update cascade on delete cascade,
+    schedule_id uuid                                   not null
+        constraint scrape_jobs_scrape_schedules_id_fk
+            references scrape_schedules
+            on update cascade on delete cascade,
+    anime_id    int                                    not null,
+    created_at  timestamptz default now()              not null,
+    updated_at  timestamptz default now()              not null
+);
+
+create unique index scrape_jobs_id_uindex
+    on scrape_jobs (id);
+
+alter table scrape_jobs
+    add constraint scrape_jobs_pk
+        primary key (id);
+
+SELECT diesel_manage_updated_at('scrape_jobs');
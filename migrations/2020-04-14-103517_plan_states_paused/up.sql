@@ -0,0 +1,4 @@
+-- plan_states --
+
+alter table plan_states
+    add column paused boolean default false not null;
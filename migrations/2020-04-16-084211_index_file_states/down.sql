@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+
+alter table index_files
+    add column pending boolean default true not null;
+
+update index_files
+set pending = false
+where state = 4;
+
+alter table index_files
+    drop column state,
+    drop column attempts,
+    drop column import_started_at,
+    drop column import_failed_at,
+    drop column imported_at,
+    drop column superseded_at;
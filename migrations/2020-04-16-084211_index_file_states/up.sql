@@ -0,0 +1,18 @@
+-- index_files --
+
+-- state: 1 - pending, 2 - importing, 3 - failed, 4 - imported, 5 - superseded
+alter table index_files
+    add column state             int     default 1 not null,
+    add column attempts          int     default 0 not null,
+    add column import_started_at timestamptz,
+    add column import_failed_at  timestamptz,
+    add column imported_at       timestamptz,
+    add column superseded_at     timestamptz;
+
+update index_files
+set state       = 4,
+    imported_at = updated_at
+where pending = false;
+
+alter table index_files
+    drop column pending;
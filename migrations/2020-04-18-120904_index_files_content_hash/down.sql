@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+alter table index_files
+    drop column content_hash;
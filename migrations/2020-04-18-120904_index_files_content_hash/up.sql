@@ -0,0 +1,4 @@
+-- index_files --
+
+alter table index_files
+    add column content_hash text;
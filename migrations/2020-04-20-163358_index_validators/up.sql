@@ -0,0 +1,16 @@
+-- index_validators --
+
+create table index_validators
+(
+    source        int                       not null,
+    etag          text,
+    last_modified text,
+    created_at    timestamptz default now() not null,
+    updated_at    timestamptz default now() not null
+);
+
+alter table index_validators
+    add constraint index_validators_pk
+        primary key (source);
+
+SELECT diesel_manage_updated_at('index_validators');
@@ -0,0 +1,35 @@
+-- This file should undo anything in `up.sql`
+
+create table failed_imports_old
+(
+    id         uuid        default uuid_generate_v4() not null,
+    index_id   uuid                                   not null,
+    title_ids  int[]                                  not null,
+    reimported bool        default false              not null,
+    created_at timestamptz default now()              not null,
+    updated_at timestamptz default now()              not null
+);
+
+insert into failed_imports_old (index_id, title_ids, created_at)
+select index_id, array_agg(title_id order by title_id), min(first_failed_at)
+from failed_imports
+group by index_id;
+
+drop table failed_imports;
+
+alter table failed_imports_old
+    rename to failed_imports;
+
+create unique index failed_imports_id_uindex
+    on failed_imports (id);
+
+alter table failed_imports
+    add constraint failed_imports_pk
+        primary key (id);
+
+alter table failed_imports
+    add constraint failed_imports_index_files_id_fk
+        foreign key (index_id) references index_files
+            on update cascade on delete cascade;
+
+SELECT diesel_manage_updated_at('failed_imports');
@@ -0,0 +1,49 @@
+-- failed_imports --
+
+-- one row per failed to import anime title instead of a list of titles per index file
+create table failed_titles
+(
+    source          int                       not null,
+    title_id        int                       not null,
+    index_id        uuid                      not null
+        constraint failed_imports_index_files_id_fk
+            references index_files
+            on update cascade on delete cascade,
+    attempts        int         default 1     not null,
+    last_error      text,
+    quarantined     bool        default false not null,
+    first_failed_at timestamptz default now() not null,
+    last_failed_at  timestamptz default now() not null,
+    created_at      timestamptz default now() not null,
+    updated_at      timestamptz default now() not null
+);
+
+-- every not reimported list a title appears in counts as a failed attempt
+insert into failed_titles (source, title_id, index_id, attempts, first_failed_at, last_failed_at)
+select distinct on (i.source, t.title_id)
+    i.source,
+    t.title_id,
+    f.index_id,
+    count(*) over w,
+    min(f.created_at) over w,
+    max(f.created_at) over w
+from failed_imports f
+    join index_files i on i.id = f.index_id
+    cross join lateral unnest(f.title_ids) as t(title_id)
+where not f.reimported
+window w as (partition by i.source, t.title_id)
+order by i.source, t.title_id, f.created_at desc;
+
+drop table failed_imports;
+
+alter table failed_titles
+    rename to failed_imports;
+
+alter table failed_imports
+    add constraint failed_imports_pk
+        primary key (source, title_id);
+
+create index failed_imports_index_id_index
+    on failed_imports (index_id);
+
+SELECT diesel_manage_updated_at('failed_imports');
@@ -0,0 +1,33 @@
+-- import_intents --
+
+-- state: 1 - sent, 2 - applied, 3 - failed
+create table import_intents
+(
+    id           uuid                          not null,
+    source       int                           not null,
+    index_id     uuid                          not null
+        constraint import_intents_index_files_id_fk
+            references index_files
+            on update cascade on delete cascade,
+    old_index_id uuid
+        constraint import_intents_old_index_files_id_fk
+            references index_files
+            on update cascade on delete set null,
+    reimport_ids int[]       default '{}'      not null,
+    skipped_ids  int[]       default '{}'      not null,
+    state        int         default 1         not null,
+    error        text,
+    sent_at      timestamptz default now()     not null,
+    finished_at  timestamptz,
+    created_at   timestamptz default now()     not null,
+    updated_at   timestamptz default now()     not null
+);
+
+alter table import_intents
+    add constraint import_intents_pk
+        primary key (id);
+
+create index import_intents_source_state_index
+    on import_intents (source, state);
+
+SELECT diesel_manage_updated_at('import_intents');
@@ -0,0 +1,24 @@
+-- scrape_intents --
+
+-- state: 1 - sent, 2 - applied, 3 - failed, 4 - abandoned
+create table scrape_intents
+(
+    id           uuid                      not null,
+    source       int                       not null,
+    may_continue bool,
+    state        int         default 1     not null,
+    error        text,
+    sent_at      timestamptz default now() not null,
+    finished_at  timestamptz,
+    created_at   timestamptz default now() not null,
+    updated_at   timestamptz default now() not null
+);
+
+alter table scrape_intents
+    add constraint scrape_intents_pk
+        primary key (id);
+
+create index scrape_intents_source_state_index
+    on scrape_intents (source, state);
+
+SELECT diesel_manage_updated_at('scrape_intents');
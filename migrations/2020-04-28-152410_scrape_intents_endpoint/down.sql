@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+alter table scrape_intents
+    drop column endpoint;
@@ -0,0 +1,5 @@
+-- scrape_intents --
+
+-- URL of the scraper instance the intent has been sent to, so it can be reissued to it
+alter table scrape_intents
+    add column endpoint text;
@@ -0,0 +1,289 @@
+use structopt::StructOpt;
+use tokio::task;
+use tracing::{error, info};
+
+use crate::{
+    db::{
+        entity::Source, import::FailedImports, index::IndexFiles, repo::Repositories,
+        ConnectionPool, QueryError,
+    },
+    plan::{retry, ClientRegistry, PlanError, ScrapePlan},
+    proto::uuid::Uuid,
+    settings::{self, Settings},
+};
+
+/// Exit code of a successful command.
+pub const EXIT_OK: i32 = 0;
+
+/// Exit code of a command that failed and shouldn't be retried without fixing the cause.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Exit code of a command that failed but may succeed if retried later (`EX_TEMPFAIL`).
+pub const EXIT_TEMPFAIL: i32 = 75;
+
+/// Decides when it's the right time to update anime index and start scraping.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "satelit-scheduler")]
+pub struct Opts {
+    /// Keeps plan state in memory instead of the database, supported by `once`,
+    /// `update-index` and `scrape` commands
+    #[structopt(long)]
+    pub no_db: bool,
+
+    /// Command to execute, `run` if not specified.
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands supported by the app.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Runs scraping plans of all enabled sources until stopped
+    Run,
+
+    /// Runs scraping plan once and exits
+    ///
+    /// Exits with 0 on success, 75 if the plan failed but may succeed if retried
+    /// and 1 otherwise.
+    Once {
+        /// Anime source to scrape: anidb, mal or ann
+        #[structopt(parse(try_from_str = parse_source))]
+        source: Source,
+    },
+
+    /// Fetches latest anime index without importing it
+    UpdateIndex {
+        /// Anime source to update index for: anidb, mal or ann
+        #[structopt(parse(try_from_str = parse_source))]
+        source: Source,
+    },
+
+    /// Imports previously fetched anime index
+    Import {
+        /// ID of the index file to import
+        #[structopt(parse(try_from_str = parse_uuid))]
+        index_id: Uuid,
+    },
+
+    /// Asks scraper service to scrape data once
+    Scrape {
+        /// Anime source to scrape: anidb, mal or ann
+        #[structopt(parse(try_from_str = parse_source))]
+        source: Source,
+    },
+
+    /// Prints pending index files and failed imports
+    Status {
+        /// Anime source to print status for, all configured sources if not specified
+        #[structopt(parse(try_from_str = parse_source))]
+        source: Option<Source>,
+    },
+}
+
+/// Runs the command keeping plan state in memory instead of the database.
+///
+/// Only commands that don't depend on previously saved state are supported.
+pub async fn without_db(config: &Settings, command: Command) -> i32 {
+    let repos = Repositories::memory();
+    match command {
+        Command::Once { source } => once(config, source, repos).await,
+        Command::UpdateIndex { source } => update_index(config, source, repos).await,
+        Command::Scrape { source } => scrape(config, source, repos).await,
+        command => {
+            error!("{:?} command requires database", command);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Runs whole scraping plan once.
+pub async fn once(config: &Settings, source: Source, repos: Repositories) -> i32 {
+    let plan = match plan(config, source, repos) {
+        Ok(plan) => plan,
+        Err(e) => return failed(e),
+    };
+
+    match plan.run().await {
+        Ok(more) => {
+            info!("scrape succeeded, has more data to scrape: {}", more);
+            EXIT_OK
+        }
+        Err(e) => failed(e),
+    }
+}
+
+/// Fetches latest anime index and saves it to the database.
+pub async fn update_index(config: &Settings, source: Source, repos: Repositories) -> i32 {
+    let plan = match plan(config, source, repos) {
+        Ok(plan) => plan,
+        Err(e) => return failed(e),
+    };
+
+    match plan.update_index().await {
+        Ok(index) => {
+            info!(
+                "latest index: {} at {}, state: {:?}, import attempts: {}",
+                &index.id, &index.file_path, index.state, index.attempts
+            );
+            EXIT_OK
+        }
+        Err(e) => failed(e),
+    }
+}
+
+/// Imports anime index with `index_id`.
+pub async fn import(config: &Settings, index_id: Uuid, pool: ConnectionPool) -> i32 {
+    let index_files = IndexFiles::new(pool.clone());
+    let index = match task::spawn_blocking(move || index_files.find(&index_id)).await {
+        Ok(Ok(index)) => index,
+        Ok(Err(e)) => return failed(e.into()),
+        Err(e) => return failed(e.into()),
+    };
+
+    let plan = match plan(config, index.source, Repositories::postgres(pool)) {
+        Ok(plan) => plan,
+        Err(e) => return failed(e),
+    };
+
+    info!("importing index: {}", &index.id);
+    match plan.import_index(index, Uuid::new()).await {
+        Ok(_) => {
+            info!("index imported");
+            EXIT_OK
+        }
+        Err(e) => failed(e),
+    }
+}
+
+/// Asks scraper service to scrape data of the `source` once.
+pub async fn scrape(config: &Settings, source: Source, repos: Repositories) -> i32 {
+    let plan = match plan(config, source, repos) {
+        Ok(plan) => plan,
+        Err(e) => return failed(e),
+    };
+
+    match plan.scrape_data(Uuid::new()).await {
+        Ok(more) => {
+            info!("scrape succeeded, has more data to scrape: {}", more);
+            EXIT_OK
+        }
+        Err(e) => failed(e),
+    }
+}
+
+/// Prints pending index files and failed imports of the `source` or all configured sources.
+pub async fn status(config: &Settings, source: Option<Source>, pool: ConnectionPool) -> i32 {
+    let sources: Vec<Source> = match source {
+        Some(source) => vec![source],
+        None => config.sources().iter().map(|s| s.name()).collect(),
+    };
+
+    let (index_files, failed_imports) = (IndexFiles::new(pool.clone()), FailedImports::new(pool));
+    let res = task::spawn_blocking(move || {
+        let mut report = vec![];
+        for source in sources {
+            let indexes = index_files.pending(source)?;
+            let failed = failed_imports.list(source)?;
+            report.push((source, indexes, failed));
+        }
+
+        Ok::<_, QueryError>(report)
+    })
+    .await;
+
+    let report = match res {
+        Ok(Ok(report)) => report,
+        Ok(Err(e)) => return failed(e.into()),
+        Err(e) => return failed(e.into()),
+    };
+
+    for (source, indexes, failed) in report {
+        println!("{:?}:", source);
+        println!("  pending index files: {}", indexes.len());
+        for index in indexes {
+            println!(
+                "    {} {} {:?} after {} attempts (created at {})",
+                &index.id, &index.file_path, index.state, index.attempts, index.created_at
+            );
+        }
+
+        let quarantined = failed.iter().filter(|f| f.quarantined).count();
+        println!(
+            "  failed imports: {} titles ({} quarantined)",
+            failed.len(),
+            quarantined
+        );
+        for import in failed {
+            println!(
+                "    {}{} after {} attempts (last failed at {} in index {}): {}",
+                import.title_id,
+                if import.quarantined {
+                    " quarantined"
+                } else {
+                    ""
+                },
+                import.attempts,
+                import.last_failed_at,
+                &import.index_id,
+                import.last_error.as_deref().unwrap_or("unknown error")
+            );
+        }
+    }
+
+    EXIT_OK
+}
+
+/// Returns scraping plan for the `source` configured the same way as the daemon does.
+fn plan(config: &Settings, source: Source, repos: Repositories) -> Result<ScrapePlan, PlanError> {
+    let clients = ClientRegistry::new(config)?.clients(source)?;
+    Ok(ScrapePlan::new(
+        source,
+        clients,
+        repos,
+        config.schedule().max_import_attempts(),
+        config.schedule().max_title_attempts(),
+    ))
+}
+
+/// Logs the error and returns exit code for it.
+fn failed(e: PlanError) -> i32 {
+    error!("command failed: {}", e.report());
+    if retry::is_retryable(&e) {
+        EXIT_TEMPFAIL
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+fn parse_source(name: &str) -> Result<Source, String> {
+    settings::parse_source(name).ok_or_else(|| format!("unknown anime source: {}", name))
+}
+
+fn parse_uuid(id: &str) -> Result<Uuid, String> {
+    id.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use super::{parse_source, parse_uuid, Command, Opts};
+    use crate::db::entity::Source;
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_source("mal"), Ok(Source::Mal));
+        assert!(parse_source("kitsu").is_err());
+
+        let id = "6ba7b810-9dad-11d1-80b4-00c04fd430c8";
+        assert_eq!(parse_uuid(id).unwrap().to_string(), id);
+        assert!(parse_uuid("not-an-id").is_err());
+
+        let opts = Opts::from_iter(&["satelit-scheduler", "--no-db", "once", "mal"]);
+        assert!(opts.no_db);
+        match opts.command {
+            Some(Command::Once { source }) => assert_eq!(source, Source::Mal),
+            command => panic!("unexpected command: {:?}", command),
+        }
+    }
+}
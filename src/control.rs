@@ -0,0 +1,187 @@
+use chrono::{DateTime, Utc};
+use tokio::sync::{watch, Notify};
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::db::entity::Source;
+
+/// Runtime control over scraping plans shared between plan runners and admin API.
+#[derive(Debug, Clone)]
+pub struct Control {
+    /// Control state of every running plan.
+    plans: Arc<HashMap<Source, PlanControl>>,
+}
+
+/// Snapshot of a plan's runtime status.
+#[derive(Debug, Clone, Default)]
+pub struct PlanStatus {
+    /// Whether plan is running right now.
+    pub running: bool,
+
+    /// When plan is going to run next time.
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+/// Control state of a single plan.
+#[derive(Debug)]
+struct PlanControl {
+    /// Wakes up plan runner to run the plan immediately.
+    trigger: Notify,
+
+    /// Notifies plan runner that the plan has been paused or resumed.
+    paused_tx: watch::Sender<bool>,
+
+    /// Current value of the pause flag.
+    paused_rx: watch::Receiver<bool>,
+
+    /// Runtime status reported by plan runner.
+    status: Mutex<PlanStatus>,
+}
+
+// MARK: impl Control
+
+impl Control {
+    /// Creates control state for plans of provided anime sources.
+    pub fn new<I>(sources: I) -> Self
+    where
+        I: IntoIterator<Item = Source>,
+    {
+        let plans = sources
+            .into_iter()
+            .map(|source| (source, PlanControl::new()))
+            .collect();
+
+        Control {
+            plans: Arc::new(plans),
+        }
+    }
+
+    /// Returns anime sources which plans can be controlled.
+    pub fn sources(&self) -> Vec<Source> {
+        self.plans.keys().copied().collect()
+    }
+
+    /// Asks plan of the `source` to run as soon as possible.
+    ///
+    /// If the plan is running right now, it will be run once again after it's finished.
+    /// Returns `false` if there's no plan for the `source`.
+    pub fn trigger(&self, source: Source) -> bool {
+        match self.plans.get(&source) {
+            Some(plan) => {
+                plan.trigger.notify();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pauses or resumes plan of the `source`.
+    ///
+    /// Paused plan is allowed to finish current run but won't be started again
+    /// until resumed. Returns `false` if there's no plan for the `source`.
+    pub fn set_paused(&self, source: Source, paused: bool) -> bool {
+        match self.plans.get(&source) {
+            Some(plan) => {
+                // receiver is owned by the plan itself so broadcast can't fail
+                let _ = plan.paused_tx.broadcast(paused);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if plan of the `source` is paused.
+    pub fn is_paused(&self, source: Source) -> bool {
+        self.plans
+            .get(&source)
+            .map_or(false, |plan| *plan.paused_rx.borrow())
+    }
+
+    /// Returns runtime status of the `source` plan.
+    pub fn status(&self, source: Source) -> Option<PlanStatus> {
+        self.plans.get(&source).map(|plan| plan.status().clone())
+    }
+
+    /// Records that plan of the `source` has started or finished running.
+    pub fn set_running(&self, source: Source, running: bool) {
+        if let Some(plan) = self.plans.get(&source) {
+            plan.status().running = running;
+        }
+    }
+
+    /// Records when plan of the `source` is going to run next time.
+    pub fn set_next_run(&self, source: Source, next_run_at: Option<DateTime<Utc>>) {
+        if let Some(plan) = self.plans.get(&source) {
+            plan.status().next_run_at = next_run_at;
+        }
+    }
+
+    /// Waits until plan of the `source` is asked to run.
+    ///
+    /// Never resolves if there's no plan for the `source`.
+    pub async fn triggered(&self, source: Source) {
+        match self.plans.get(&source) {
+            Some(plan) => plan.trigger.notified().await,
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Waits until plan of the `source` is resumed. Returns immediately if it's not paused.
+    pub async fn resumed(&self, source: Source) {
+        let mut paused = match self.plans.get(&source) {
+            Some(plan) => plan.paused_rx.clone(),
+            None => return,
+        };
+
+        while *paused.borrow() {
+            if paused.recv().await.is_none() {
+                return;
+            }
+        }
+    }
+}
+
+// MARK: impl PlanControl
+
+impl PlanControl {
+    fn new() -> Self {
+        let (paused_tx, paused_rx) = watch::channel(false);
+        PlanControl {
+            trigger: Notify::new(),
+            paused_tx,
+            paused_rx,
+            status: Mutex::new(PlanStatus::default()),
+        }
+    }
+
+    fn status(&self) -> MutexGuard<'_, PlanStatus> {
+        // status is only assigned while locked so it's safe to ignore poisoning
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Control;
+    use crate::db::entity::Source;
+
+    #[tokio::test]
+    async fn test_pause_and_trigger() {
+        let control = Control::new(vec![Source::Anidb]);
+        assert!(!control.is_paused(Source::Anidb));
+        assert!(!control.set_paused(Source::Mal, true));
+
+        assert!(control.set_paused(Source::Anidb, true));
+        assert!(control.is_paused(Source::Anidb));
+
+        assert!(control.set_paused(Source::Anidb, false));
+        control.resumed(Source::Anidb).await;
+
+        // trigger is remembered until plan runner waits for it
+        assert!(control.trigger(Source::Anidb));
+        control.triggered(Source::Anidb).await;
+    }
+}
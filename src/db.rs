@@ -1,12 +1,22 @@
 pub mod entity;
 pub mod import;
 pub mod index;
+pub mod intents;
+pub mod lock;
+pub mod memory;
+pub mod repo;
+pub mod runs;
 pub mod schema;
+pub mod state;
+pub mod tasks;
 
 use std::fmt;
 
 use diesel::{r2d2, PgConnection};
-pub use diesel::{r2d2::PoolError, result::Error as UnderlyingError};
+pub use diesel::{
+    r2d2::PoolError,
+    result::{ConnectionError, Error as UnderlyingError},
+};
 
 use crate::settings;
 
@@ -24,6 +34,8 @@ pub enum QueryError {
     PoolFailed(PoolError),
     /// Failed to perform db query
     QueryFailed(UnderlyingError),
+    /// Failed to open db connection outside of connection pool
+    ConnectionFailed(ConnectionError),
 }
 
 pub fn new_connection_pool(settings: &settings::Db) -> Result<ConnectionPool, PoolError> {
@@ -42,6 +54,16 @@ impl ConnectionPool {
     pub fn get(&self) -> Result<PgPooledConnection, PoolError> {
         self.0.get()
     }
+
+    /// Returns current state of the pool.
+    pub fn state(&self) -> r2d2::State {
+        self.0.state()
+    }
+
+    /// Returns maximum number of connections managed by the pool.
+    pub fn max_size(&self) -> u32 {
+        self.0.max_size()
+    }
 }
 
 impl std::fmt::Debug for ConnectionPool {
@@ -64,6 +86,12 @@ impl From<UnderlyingError> for QueryError {
     }
 }
 
+impl From<ConnectionError> for QueryError {
+    fn from(e: ConnectionError) -> Self {
+        QueryError::ConnectionFailed(e)
+    }
+}
+
 impl fmt::Display for QueryError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use QueryError::*;
@@ -71,8 +99,19 @@ impl fmt::Display for QueryError {
         match *self {
             PoolFailed(ref e) => <PoolError as fmt::Display>::fmt(&e, f),
             QueryFailed(ref e) => <UnderlyingError as fmt::Display>::fmt(&e, f),
+            ConnectionFailed(ref e) => <ConnectionError as fmt::Display>::fmt(&e, f),
         }
     }
 }
 
-impl std::error::Error for QueryError {}
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use QueryError::*;
+
+        match *self {
+            PoolFailed(ref e) => Some(e),
+            QueryFailed(ref e) => Some(e),
+            ConnectionFailed(ref e) => Some(e),
+        }
+    }
+}
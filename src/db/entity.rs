@@ -3,17 +3,88 @@ mod convert;
 use chrono::{DateTime, Utc};
 use diesel::sql_types::Integer;
 
+use std::collections::HashSet;
+
 use crate::{
-    db::schema::{failed_imports, index_files},
+    db::schema::{
+        import_intents, index_files, plan_runs, scrape_intents, scrape_jobs, scrape_schedules,
+        scrape_tasks,
+    },
     proto::uuid::Uuid,
 };
 
 /// Represents anime entry location in external database.
 #[repr(C)]
 #[sql_type = "Integer"]
-#[derive(Debug, Clone, Copy, FromSqlRow, AsExpression)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromSqlRow, AsExpression)]
 pub enum Source {
     Anidb = 1,
+    Mal = 2,
+    Ann = 3,
+}
+
+/// Represents a step of scraping plan execution.
+#[repr(C)]
+#[sql_type = "Integer"]
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
+pub enum Phase {
+    UpdateIndex = 1,
+    ImportIndex = 2,
+    ScrapeData = 3,
+}
+
+/// Represents result of scraping plan execution.
+#[repr(C)]
+#[sql_type = "Integer"]
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
+pub enum Outcome {
+    Running = 1,
+    Succeeded = 2,
+    Failed = 3,
+    Interrupted = 4,
+}
+
+/// Represents scraping state of an anime title.
+#[repr(C)]
+#[sql_type = "Integer"]
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
+pub enum ScheduleState {
+    Pending = 1,
+    Processing = 2,
+}
+
+/// Represents import state of an anime index file.
+#[repr(C)]
+#[sql_type = "Integer"]
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
+pub enum IndexState {
+    /// Import has not been attempted yet.
+    Pending = 1,
+    /// Import is in progress.
+    Importing = 2,
+    /// Last import attempt has failed.
+    Failed = 3,
+    /// Index has been imported.
+    Imported = 4,
+    /// Newer index has been received before this one was imported.
+    Superseded = 5,
+    /// Index has the same content as already imported one so it's not imported.
+    Unchanged = 6,
+}
+
+/// Represents state of an intent sent to external service.
+#[repr(C)]
+#[sql_type = "Integer"]
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
+pub enum IntentState {
+    /// Intent has been sent and the service hasn't responded yet.
+    Sent = 1,
+    /// Result of the intent has been applied.
+    Applied = 2,
+    /// Service has failed to process the intent.
+    Failed = 3,
+    /// Intent has been left unfinished after the app has stopped.
+    Abandoned = 4,
 }
 
 /// Represents an index file of all anime entries in external database.
@@ -22,18 +93,228 @@ pub struct IndexFile {
     pub id: Uuid,
     pub source: Source,
     pub file_path: String,
-    pub pending: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub state: IndexState,
+    pub attempts: i32,
+    pub import_started_at: Option<DateTime<Utc>>,
+    pub import_failed_at: Option<DateTime<Utc>>,
+    pub imported_at: Option<DateTime<Utc>>,
+    pub superseded_at: Option<DateTime<Utc>>,
+    pub content_hash: Option<String>,
 }
 
-/// Represents list of failed anime imports for an index file.
-#[derive(Debug, Clone, Queryable, Identifiable)]
+/// Represents HTTP cache validators of the latest index file response for an external database.
+#[derive(Debug, Clone, Queryable)]
+pub struct IndexValidators {
+    pub source: Source,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents anime title that failed to import.
+#[derive(Debug, Clone, Queryable)]
 pub struct FailedImport {
+    pub source: Source,
+    pub title_id: i32,
+    pub index_id: Uuid,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub quarantined: bool,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents an intent sent to importer service to import an index file.
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct ImportIntent {
     pub id: Uuid,
+    pub source: Source,
     pub index_id: Uuid,
-    pub title_ids: Vec<i32>,
-    pub reimported: bool,
+    pub old_index_id: Option<Uuid>,
+    pub reimport_ids: Vec<i32>,
+    pub skipped_ids: Vec<i32>,
+    pub state: IntentState,
+    pub error: Option<String>,
+    pub sent_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents an intent sent to scraping service to scrape anime data.
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct ScrapeIntent {
+    pub id: Uuid,
+    pub source: Source,
+    pub may_continue: Option<bool>,
+    pub state: IntentState,
+    pub error: Option<String>,
+    pub sent_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// URL of the scraper instance the intent has been sent to.
+    pub endpoint: Option<String>,
+}
+
+/// Represents a single scraping plan execution.
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct PlanRun {
+    pub id: Uuid,
+    pub source: Source,
+    pub phase: Phase,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    pub import_intent_id: Option<Uuid>,
+    pub scrape_intent_id: Option<Uuid>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents persistent state of scraping plan for an external database.
+#[derive(Debug, Clone, Queryable)]
+pub struct PlanState {
+    pub source: Source,
+    pub consecutive_failures: i32,
+    pub degraded: bool,
+    pub last_error: Option<String>,
+    pub degraded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub paused: bool,
+}
+
+/// Represents an anime title that should be periodically scraped.
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct ScrapeSchedule {
+    pub id: Uuid,
+    pub source: Source,
+    pub anime_id: i32,
+    pub state: ScheduleState,
+    pub next_update_at: DateTime<Utc>,
+    pub update_count: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents a scraping task handed out to a scraper.
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct ScrapeTask {
+    pub id: Uuid,
+    pub source: Source,
+    pub finished: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+/// Represents a single anime title to scrape as part of a scraping task.
+#[derive(Debug, Clone, Queryable, Identifiable)]
+pub struct ScrapeJob {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub schedule_id: Uuid,
+    pub anime_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// MARK: impl IndexFile
+
+impl IndexFile {
+    /// Returns `true` if the index should be imported by importer service.
+    ///
+    /// Failed or interrupted imports are retried until `max_attempts` is reached.
+    pub fn needs_import(&self, max_attempts: u32) -> bool {
+        match self.state {
+            IndexState::Pending => true,
+            IndexState::Importing | IndexState::Failed => (self.attempts as u32) < max_attempts,
+            IndexState::Imported | IndexState::Superseded | IndexState::Unchanged => false,
+        }
+    }
+}
+
+// MARK: impl ImportIntent
+
+impl ImportIntent {
+    /// Splits titles sent to be reimported into those that have been reimported and
+    /// those that the importer has skipped again.
+    ///
+    /// Skipped titles that weren't sent for reimport are new failures and aren't returned.
+    pub fn split_reimported(&self, skipped_ids: &[i32]) -> (Vec<i32>, Vec<i32>) {
+        let skipped: HashSet<i32> = skipped_ids.iter().copied().collect();
+        self.reimport_ids
+            .iter()
+            .copied()
+            .partition(|id| !skipped.contains(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{ImportIntent, IndexFile, IndexState, IntentState, Source};
+    use crate::proto::uuid::Uuid;
+
+    fn index(state: IndexState, attempts: i32) -> IndexFile {
+        IndexFile {
+            id: Uuid::new(),
+            source: Source::Anidb,
+            file_path: "anidb/index.json.gz".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            state,
+            attempts,
+            import_started_at: None,
+            import_failed_at: None,
+            imported_at: None,
+            superseded_at: None,
+            content_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_needs_import() {
+        assert!(index(IndexState::Pending, 0).needs_import(3));
+        assert!(index(IndexState::Failed, 2).needs_import(3));
+        assert!(index(IndexState::Importing, 1).needs_import(3));
+        assert!(!index(IndexState::Failed, 3).needs_import(3));
+        assert!(!index(IndexState::Imported, 1).needs_import(3));
+        assert!(!index(IndexState::Superseded, 0).needs_import(3));
+        assert!(!index(IndexState::Unchanged, 0).needs_import(3));
+    }
+
+    #[test]
+    fn test_split_reimported() {
+        let now = Utc::now();
+        let intent = ImportIntent {
+            id: Uuid::new(),
+            source: Source::Anidb,
+            index_id: Uuid::new(),
+            old_index_id: None,
+            reimport_ids: vec![1, 2, 3, 4],
+            skipped_ids: vec![],
+            state: IntentState::Sent,
+            error: None,
+            sent_at: now,
+            finished_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let (reimported, carried) = intent.split_reimported(&[2, 4, 5]);
+        assert_eq!(reimported, vec![1, 3]);
+        assert_eq!(carried, vec![2, 4]);
+
+        let (reimported, carried) = intent.split_reimported(&[]);
+        assert_eq!(reimported, vec![1, 2, 3, 4]);
+        assert!(carried.is_empty());
+    }
+}
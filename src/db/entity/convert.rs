@@ -8,7 +8,7 @@ use diesel::{
     sql_types::{Integer, Uuid},
 };
 
-use super::Source;
+use super::{IndexState, IntentState, Outcome, Phase, ScheduleState, Source};
 use crate::proto::uuid;
 
 impl<DB> FromSql<Integer, DB> for Source
@@ -19,6 +19,8 @@ where
     fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
         match i32::from_sql(bytes)? {
             1 => Ok(Source::Anidb),
+            2 => Ok(Source::Mal),
+            3 => Ok(Source::Ann),
             x => Err(format!("Unrecognized Source case: {}", x).into()),
         }
     }
@@ -34,6 +36,145 @@ where
     }
 }
 
+// MARK: impl Phase
+
+impl<DB> FromSql<Integer, DB> for Phase
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(Phase::UpdateIndex),
+            2 => Ok(Phase::ImportIndex),
+            3 => Ok(Phase::ScrapeData),
+            x => Err(format!("Unrecognized Phase case: {}", x).into()),
+        }
+    }
+}
+
+impl<DB> ToSql<Integer, DB> for Phase
+where
+    DB: Backend,
+    i32: ToSql<Integer, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        (*self as i32).to_sql(out)
+    }
+}
+
+// MARK: impl Outcome
+
+impl<DB> FromSql<Integer, DB> for Outcome
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(Outcome::Running),
+            2 => Ok(Outcome::Succeeded),
+            3 => Ok(Outcome::Failed),
+            4 => Ok(Outcome::Interrupted),
+            x => Err(format!("Unrecognized Outcome case: {}", x).into()),
+        }
+    }
+}
+
+impl<DB> ToSql<Integer, DB> for Outcome
+where
+    DB: Backend,
+    i32: ToSql<Integer, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        (*self as i32).to_sql(out)
+    }
+}
+
+// MARK: impl ScheduleState
+
+impl<DB> FromSql<Integer, DB> for ScheduleState
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(ScheduleState::Pending),
+            2 => Ok(ScheduleState::Processing),
+            x => Err(format!("Unrecognized ScheduleState case: {}", x).into()),
+        }
+    }
+}
+
+impl<DB> ToSql<Integer, DB> for ScheduleState
+where
+    DB: Backend,
+    i32: ToSql<Integer, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        (*self as i32).to_sql(out)
+    }
+}
+
+// MARK: impl IndexState
+
+impl<DB> FromSql<Integer, DB> for IndexState
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(IndexState::Pending),
+            2 => Ok(IndexState::Importing),
+            3 => Ok(IndexState::Failed),
+            4 => Ok(IndexState::Imported),
+            5 => Ok(IndexState::Superseded),
+            6 => Ok(IndexState::Unchanged),
+            x => Err(format!("Unrecognized IndexState case: {}", x).into()),
+        }
+    }
+}
+
+impl<DB> ToSql<Integer, DB> for IndexState
+where
+    DB: Backend,
+    i32: ToSql<Integer, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        (*self as i32).to_sql(out)
+    }
+}
+
+// MARK: impl IntentState
+
+impl<DB> FromSql<Integer, DB> for IntentState
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(IntentState::Sent),
+            2 => Ok(IntentState::Applied),
+            3 => Ok(IntentState::Failed),
+            4 => Ok(IntentState::Abandoned),
+            x => Err(format!("Unrecognized IntentState case: {}", x).into()),
+        }
+    }
+}
+
+impl<DB> ToSql<Integer, DB> for IntentState
+where
+    DB: Backend,
+    i32: ToSql<Integer, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        (*self as i32).to_sql(out)
+    }
+}
+
 // MARK: impl Uuid
 
 #[derive(FromSqlRow, AsExpression)]
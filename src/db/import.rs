@@ -1,4 +1,4 @@
-use diesel::prelude::*;
+use diesel::{dsl::now, pg::upsert::excluded, prelude::*, PgConnection};
 
 use crate::db::{
     entity::{FailedImport, IndexFile, Source},
@@ -15,41 +15,124 @@ impl FailedImports {
         FailedImports { pool }
     }
 
-    pub fn create(&self, index: &IndexFile, ids: &[i32]) -> Result<FailedImport, QueryError> {
+    /// Records failed import attempt of titles from the index file.
+    ///
+    /// Titles that have failed to import `max_attempts` times are quarantined and
+    /// won't be reimported anymore.
+    pub fn record(
+        &self,
+        index: &IndexFile,
+        ids: &[i32],
+        err: &str,
+        max_attempts: u32,
+    ) -> Result<Vec<FailedImport>, QueryError> {
+        let conn = self.pool.get()?;
+        let values = record_titles(&conn, index, ids, err, max_attempts)?;
+        Ok(values)
+    }
+
+    /// Returns titles of the source that should be reimported.
+    pub fn eligible(&self, src: Source) -> Result<Vec<FailedImport>, QueryError> {
         use crate::db::schema::failed_imports::dsl::*;
 
         let conn = self.pool.get()?;
-        let value = diesel::insert_into(failed_imports)
-            .values((index_id.eq(&index.id), title_ids.eq(ids)))
-            .get_result(&conn)?;
+        let values = failed_imports
+            .filter(source.eq(src))
+            .filter(quarantined.eq(false))
+            .order(title_id.asc())
+            .load(&conn)?;
 
-        Ok(value)
+        Ok(values)
     }
 
-    pub fn with_source(&self, src: Source) -> Result<Option<FailedImport>, QueryError> {
-        use crate::db::schema::{failed_imports, index_files};
+    /// Forgets titles of the source that have been successfully reimported.
+    pub fn mark_reimported(&self, src: Source, ids: &[i32]) -> Result<usize, QueryError> {
+        let conn = self.pool.get()?;
+        let count = forget_titles(&conn, src, ids)?;
+        Ok(count)
+    }
+
+    /// Returns number of titles waiting to be reimported and number of quarantined ones.
+    pub fn count_titles(&self, src: Source) -> Result<(i64, i64), QueryError> {
+        use crate::db::schema::failed_imports::dsl::*;
 
         let conn = self.pool.get()?;
-        let value = failed_imports::table
-            .inner_join(index_files::table)
-            .filter(failed_imports::reimported.eq(false))
-            .filter(index_files::source.eq(src as i32))
-            .order(failed_imports::created_at.desc())
-            .select(failed_imports::all_columns)
-            .first::<FailedImport>(&conn)
-            .optional()?;
-
-        Ok(value)
+        let count = |q: bool| {
+            failed_imports
+                .filter(source.eq(src))
+                .filter(quarantined.eq(q))
+                .count()
+                .get_result::<i64>(&conn)
+        };
+
+        Ok((count(false)?, count(true)?))
     }
 
-    pub fn mark_reimported(&self, failed: FailedImport) -> Result<FailedImport, QueryError> {
+    /// Returns all failed to import titles of the source including quarantined ones.
+    pub fn list(&self, src: Source) -> Result<Vec<FailedImport>, QueryError> {
         use crate::db::schema::failed_imports::dsl::*;
 
         let conn = self.pool.get()?;
-        let value = diesel::update(&failed)
-            .set(reimported.eq(true))
-            .get_result(&conn)?;
+        let values = failed_imports
+            .filter(source.eq(src))
+            .order((quarantined.asc(), title_id.asc()))
+            .load(&conn)?;
 
-        Ok(value)
+        Ok(values)
     }
 }
+
+/// Records failed import attempt of titles from the index file using the connection.
+pub(crate) fn record_titles(
+    conn: &PgConnection,
+    index: &IndexFile,
+    ids: &[i32],
+    err: &str,
+    max_attempts: u32,
+) -> QueryResult<Vec<FailedImport>> {
+    use crate::db::schema::failed_imports::dsl::*;
+
+    let mut ids = ids.to_vec();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let max_attempts = max_attempts as i32;
+    let rows: Vec<_> = ids
+        .iter()
+        .map(|id| {
+            (
+                source.eq(index.source),
+                title_id.eq(*id),
+                index_id.eq(&index.id),
+                last_error.eq(err),
+                quarantined.eq(max_attempts <= 1),
+            )
+        })
+        .collect();
+
+    diesel::insert_into(failed_imports)
+        .values(&rows)
+        .on_conflict((source, title_id))
+        .do_update()
+        .set((
+            index_id.eq(excluded(index_id)),
+            attempts.eq(attempts + 1),
+            last_error.eq(excluded(last_error)),
+            quarantined.eq(quarantined.or(attempts.ge(max_attempts - 1))),
+            last_failed_at.eq(now),
+        ))
+        .get_results(conn)
+}
+
+/// Forgets reimported titles of the source using the connection.
+pub(crate) fn forget_titles(conn: &PgConnection, src: Source, ids: &[i32]) -> QueryResult<usize> {
+    use crate::db::schema::failed_imports::dsl::*;
+
+    let query = failed_imports
+        .filter(source.eq(src))
+        .filter(title_id.eq_any(ids));
+    diesel::delete(query).execute(conn)
+}
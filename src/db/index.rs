@@ -1,10 +1,20 @@
-use diesel::prelude::*;
+use diesel::{dsl::now, prelude::*, PgConnection};
 
-use crate::db::{
-    entity::{IndexFile, Source},
-    ConnectionPool, QueryError,
+use crate::{
+    db::{
+        entity::{IndexFile, IndexState, IndexValidators, Source},
+        ConnectionPool, QueryError, UnderlyingError,
+    },
+    proto::uuid::Uuid,
 };
 
+/// Index file states that are not imported yet but may be.
+pub(crate) const UNPROCESSED: &[IndexState] = &[
+    IndexState::Pending,
+    IndexState::Importing,
+    IndexState::Failed,
+];
+
 #[derive(Debug, Clone)]
 pub struct IndexFiles {
     pool: ConnectionPool,
@@ -15,16 +25,60 @@ impl IndexFiles {
         IndexFiles { pool }
     }
 
-    pub fn queue(&self, new_path: &str, src: Source) -> Result<IndexFile, QueryError> {
+    /// Saves new index file and marks older not imported index files as superseded.
+    ///
+    /// Returns existing record if the index file is already known. If `hash` matches
+    /// content hash of the last imported index, new index file is marked as unchanged.
+    pub fn queue(
+        &self,
+        new_path: &str,
+        src: Source,
+        hash: Option<&str>,
+    ) -> Result<IndexFile, QueryError> {
         use crate::db::schema::index_files::dsl::*;
 
         let conn = self.pool.get()?;
-        let index = diesel::insert_into(index_files)
-            .values((file_path.eq(new_path), source.eq(src)))
-            .on_conflict(file_path)
-            .do_update()
-            .set(file_path.eq(new_path))
-            .get_result(&conn)?;
+        let index = conn.transaction::<_, UnderlyingError, _>(|| {
+            let mut index: IndexFile = diesel::insert_into(index_files)
+                .values((
+                    file_path.eq(new_path),
+                    source.eq(src),
+                    content_hash.eq(hash),
+                ))
+                .on_conflict(file_path)
+                .do_update()
+                .set(file_path.eq(new_path))
+                .get_result(&conn)?;
+
+            if index.state != IndexState::Pending {
+                return Ok(index);
+            }
+
+            let imported_hash: Option<Option<String>> = index_files
+                .select(content_hash)
+                .filter(source.eq(src))
+                .filter(state.eq(IndexState::Imported))
+                .order_by(imported_at.desc())
+                .first(&conn)
+                .optional()?;
+
+            if hash.is_some() && imported_hash.flatten().as_deref() == hash {
+                index = diesel::update(index_files.find(&index.id))
+                    .set(state.eq(IndexState::Unchanged))
+                    .get_result(&conn)?;
+            }
+
+            diesel::update(
+                index_files
+                    .filter(source.eq(src))
+                    .filter(id.ne(&index.id))
+                    .filter(state.eq_any(UNPROCESSED)),
+            )
+            .set((state.eq(IndexState::Superseded), superseded_at.eq(now)))
+            .execute(&conn)?;
+
+            Ok(index)
+        })?;
 
         Ok(index)
     }
@@ -32,32 +86,154 @@ impl IndexFiles {
     pub fn latest_processed(&self, latest: &IndexFile) -> Result<Option<IndexFile>, QueryError> {
         use crate::db::schema::index_files::dsl::*;
 
-        if !latest.pending {
+        if latest.state == IndexState::Imported {
             return Ok(Some(latest.clone()));
         }
 
         let conn = self.pool.get()?;
-        let index: Result<IndexFile, _> = index_files
-            .select(index_files::all_columns())
-            .filter(pending.eq(false))
-            .order_by(updated_at.desc())
-            .first(&conn);
+        let index = index_files
+            .filter(source.eq(latest.source))
+            .filter(state.eq(IndexState::Imported))
+            .order_by(imported_at.desc())
+            .first(&conn)
+            .optional()?;
 
-        match index {
-            Ok(index) => Ok(Some(index)),
-            Err(diesel::result::Error::NotFound) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+        Ok(index)
     }
 
-    pub fn mark_processed(&self, index_file: IndexFile) -> Result<IndexFile, QueryError> {
+    /// Records new import attempt of the index file.
+    pub fn mark_importing(&self, index_file: &IndexFile) -> Result<IndexFile, QueryError> {
         use crate::db::schema::index_files::dsl::*;
 
         let conn = self.pool.get()?;
-        let new_index = diesel::update(index_files.find(index_file.id))
-            .set(pending.eq(false))
+        let index = diesel::update(index_files.find(&index_file.id))
+            .set((
+                state.eq(IndexState::Importing),
+                attempts.eq(attempts + 1),
+                import_started_at.eq(now),
+            ))
             .get_result(&conn)?;
 
+        Ok(index)
+    }
+
+    /// Records failed import attempt of the index file.
+    ///
+    /// Returns `None` if the index file is not being imported, so it's attempt has not
+    /// been counted and it's state is left as is.
+    pub fn mark_failed(&self, index_id: &Uuid) -> Result<Option<IndexFile>, QueryError> {
+        use crate::db::schema::index_files::dsl::*;
+
+        let conn = self.pool.get()?;
+        let index = diesel::update(
+            index_files
+                .find(index_id)
+                .filter(state.eq(IndexState::Importing)),
+        )
+        .set((state.eq(IndexState::Failed), import_failed_at.eq(now)))
+        .get_result(&conn)
+        .optional()?;
+
+        Ok(index)
+    }
+
+    pub fn mark_processed(&self, index_file: IndexFile) -> Result<IndexFile, QueryError> {
+        let conn = self.pool.get()?;
+        let new_index = mark_imported(&conn, &index_file.id)?;
         Ok(new_index)
     }
+
+    /// Returns number of index files of the source that are not imported yet.
+    pub fn count_pending(&self, src: Source) -> Result<i64, QueryError> {
+        use crate::db::schema::index_files::dsl::*;
+
+        let conn = self.pool.get()?;
+        let count = index_files
+            .filter(source.eq(src))
+            .filter(state.eq_any(UNPROCESSED))
+            .count()
+            .get_result(&conn)?;
+
+        Ok(count)
+    }
+
+    pub fn find(&self, index_id: &Uuid) -> Result<IndexFile, QueryError> {
+        use crate::db::schema::index_files::dsl::*;
+
+        let conn = self.pool.get()?;
+        let index = index_files.find(index_id).first(&conn)?;
+
+        Ok(index)
+    }
+
+    /// Returns index files of the source that are not imported yet.
+    pub fn pending(&self, src: Source) -> Result<Vec<IndexFile>, QueryError> {
+        use crate::db::schema::index_files::dsl::*;
+
+        let conn = self.pool.get()?;
+        let indexes = index_files
+            .filter(source.eq(src))
+            .filter(state.eq_any(UNPROCESSED))
+            .order(created_at.asc())
+            .load(&conn)?;
+
+        Ok(indexes)
+    }
+
+    /// Returns most recently received index file of the source.
+    pub fn latest(&self, src: Source) -> Result<Option<IndexFile>, QueryError> {
+        use crate::db::schema::index_files::dsl::*;
+
+        let conn = self.pool.get()?;
+        let index = index_files
+            .filter(source.eq(src))
+            .order_by(created_at.desc())
+            .first(&conn)
+            .optional()?;
+
+        Ok(index)
+    }
+
+    /// Returns cache validators of the last latest index file response for the source.
+    pub fn validators(&self, src: Source) -> Result<Option<IndexValidators>, QueryError> {
+        use crate::db::schema::index_validators::dsl::*;
+
+        let conn = self.pool.get()?;
+        let value = index_validators.find(src).first(&conn).optional()?;
+
+        Ok(value)
+    }
+
+    /// Saves cache validators of the latest index file response for the source.
+    pub fn save_validators(
+        &self,
+        src: Source,
+        new_etag: Option<&str>,
+        new_last_modified: Option<&str>,
+    ) -> Result<IndexValidators, QueryError> {
+        use crate::db::schema::index_validators::dsl::*;
+
+        let conn = self.pool.get()?;
+        let value = diesel::insert_into(index_validators)
+            .values((
+                source.eq(src),
+                etag.eq(new_etag),
+                last_modified.eq(new_last_modified),
+            ))
+            .on_conflict(source)
+            .do_update()
+            .set((etag.eq(new_etag), last_modified.eq(new_last_modified)))
+            .get_result(&conn)?;
+
+        Ok(value)
+    }
+}
+
+/// Marks index file with the `index_id` as imported using the connection.
+pub(crate) fn mark_imported(conn: &PgConnection, index_id: &Uuid) -> QueryResult<IndexFile> {
+    use crate::db::schema::index_files::dsl::*;
+
+    diesel::update(index_files.find(index_id))
+        .set((state.eq(IndexState::Imported), imported_at.eq(now)))
+        .get_result(conn)
 }
@@ -0,0 +1,273 @@
+use diesel::{dsl::now, prelude::*};
+
+use crate::{
+    db::{
+        entity::{FailedImport, ImportIntent, IndexFile, IntentState, ScrapeIntent, Source},
+        import, index, tasks, ConnectionPool, QueryError, UnderlyingError,
+    },
+    proto::uuid::Uuid,
+};
+
+#[derive(Debug, Clone)]
+pub struct ImportIntents {
+    pool: ConnectionPool,
+}
+
+impl ImportIntents {
+    pub fn new(pool: ConnectionPool) -> Self {
+        ImportIntents { pool }
+    }
+
+    /// Records import intent of the index file before it's sent to importer service.
+    pub fn create(
+        &self,
+        intent_id: &Uuid,
+        index: &IndexFile,
+        old_index: Option<&IndexFile>,
+        ids: &[i32],
+    ) -> Result<ImportIntent, QueryError> {
+        use crate::db::schema::import_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = diesel::insert_into(import_intents)
+            .values((
+                id.eq(intent_id),
+                source.eq(index.source),
+                index_id.eq(&index.id),
+                old_index_id.eq(old_index.map(|i| &i.id)),
+                reimport_ids.eq(ids),
+            ))
+            .get_result(&conn)?;
+
+        Ok(intent)
+    }
+
+    /// Applies result of the import intent in a single transaction.
+    ///
+    /// Imported titles are scheduled for scraping, reimported ones are forgotten, failed
+    /// attempt of skipped ones is recorded and the index file is marked as imported.
+    /// Returns `None` if the intent has already been applied, so replaying the same
+    /// result is harmless.
+    pub fn apply(
+        &self,
+        intent_id: &Uuid,
+        imported: &[i32],
+        skipped: &[i32],
+        max_attempts: u32,
+    ) -> Result<Option<Vec<FailedImport>>, QueryError> {
+        use crate::db::schema::import_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let failed = conn.transaction::<_, UnderlyingError, _>(|| {
+            let intent: ImportIntent = import_intents.find(intent_id).for_update().first(&conn)?;
+            if intent.state == IntentState::Applied {
+                return Ok(None);
+            }
+
+            tasks::schedule(&conn, intent.source, imported)?;
+            let (reimported, _) = intent.split_reimported(skipped);
+            import::forget_titles(&conn, intent.source, &reimported)?;
+
+            let index = index::mark_imported(&conn, &intent.index_id)?;
+            let err = format!("skipped by importer in index {}", &index.file_path);
+            let failed = import::record_titles(&conn, &index, skipped, &err, max_attempts)?;
+
+            diesel::update(import_intents.find(intent_id))
+                .set((
+                    state.eq(IntentState::Applied),
+                    skipped_ids.eq(skipped),
+                    error.eq(None::<String>),
+                    finished_at.eq(now),
+                ))
+                .execute(&conn)?;
+
+            Ok(Some(failed))
+        })?;
+
+        Ok(failed)
+    }
+
+    /// Records that importer service has failed to process the intent.
+    ///
+    /// Returns `None` if the intent is not waiting for a result anymore.
+    pub fn mark_failed(
+        &self,
+        intent_id: &Uuid,
+        err: &str,
+    ) -> Result<Option<ImportIntent>, QueryError> {
+        self.finish(intent_id, IntentState::Failed, Some(err))
+    }
+
+    /// Records that the intent has never completed and won't be sent again.
+    ///
+    /// Returns `None` if the intent is not waiting for a result anymore.
+    pub fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ImportIntent>, QueryError> {
+        self.finish(intent_id, IntentState::Abandoned, None)
+    }
+
+    /// Returns import intents of the source that have been sent but never completed.
+    pub fn outstanding(&self, src: Source) -> Result<Vec<ImportIntent>, QueryError> {
+        use crate::db::schema::import_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intents = import_intents
+            .filter(source.eq(src))
+            .filter(state.eq(IntentState::Sent))
+            .order(sent_at.asc())
+            .load(&conn)?;
+
+        Ok(intents)
+    }
+
+    /// Returns import intent with the `intent_id`.
+    pub fn find(&self, intent_id: &Uuid) -> Result<ImportIntent, QueryError> {
+        use crate::db::schema::import_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = import_intents.find(intent_id).first(&conn)?;
+
+        Ok(intent)
+    }
+
+    /// Moves the intent that is waiting for a result to the final `new_state`.
+    fn finish(
+        &self,
+        intent_id: &Uuid,
+        new_state: IntentState,
+        err: Option<&str>,
+    ) -> Result<Option<ImportIntent>, QueryError> {
+        use crate::db::schema::import_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = diesel::update(
+            import_intents
+                .find(intent_id)
+                .filter(state.eq(IntentState::Sent)),
+        )
+        .set((state.eq(new_state), error.eq(err), finished_at.eq(now)))
+        .get_result(&conn)
+        .optional()?;
+
+        Ok(intent)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrapeIntents {
+    pool: ConnectionPool,
+}
+
+impl ScrapeIntents {
+    pub fn new(pool: ConnectionPool) -> Self {
+        ScrapeIntents { pool }
+    }
+
+    /// Records scrape intent of the source before it's sent to the scraper at `url`.
+    pub fn create(
+        &self,
+        intent_id: &Uuid,
+        src: Source,
+        url: &str,
+    ) -> Result<ScrapeIntent, QueryError> {
+        use crate::db::schema::scrape_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = diesel::insert_into(scrape_intents)
+            .values((id.eq(intent_id), source.eq(src), endpoint.eq(url)))
+            .get_result(&conn)?;
+
+        Ok(intent)
+    }
+
+    /// Records result of the scrape intent.
+    ///
+    /// Returns `None` if the result has already been recorded.
+    pub fn complete(
+        &self,
+        intent_id: &Uuid,
+        more: bool,
+    ) -> Result<Option<ScrapeIntent>, QueryError> {
+        use crate::db::schema::scrape_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = diesel::update(
+            scrape_intents
+                .find(intent_id)
+                .filter(state.ne(IntentState::Applied)),
+        )
+        .set((
+            state.eq(IntentState::Applied),
+            may_continue.eq(more),
+            error.eq(None::<String>),
+            finished_at.eq(now),
+        ))
+        .get_result(&conn)
+        .optional()?;
+
+        Ok(intent)
+    }
+
+    /// Records that scraping service has failed to process the intent.
+    ///
+    /// Returns `None` if the intent is not waiting for a result anymore.
+    pub fn mark_failed(
+        &self,
+        intent_id: &Uuid,
+        err: &str,
+    ) -> Result<Option<ScrapeIntent>, QueryError> {
+        self.finish(intent_id, IntentState::Failed, Some(err))
+    }
+
+    /// Records that the intent has never completed and won't be sent again.
+    ///
+    /// Returns `None` if the intent is not waiting for a result anymore.
+    pub fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ScrapeIntent>, QueryError> {
+        self.finish(intent_id, IntentState::Abandoned, None)
+    }
+
+    /// Returns scrape intent with the `intent_id`.
+    pub fn find(&self, intent_id: &Uuid) -> Result<ScrapeIntent, QueryError> {
+        use crate::db::schema::scrape_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = scrape_intents.find(intent_id).first(&conn)?;
+
+        Ok(intent)
+    }
+
+    /// Returns scrape intents of the source that have been sent but never completed.
+    pub fn outstanding(&self, src: Source) -> Result<Vec<ScrapeIntent>, QueryError> {
+        use crate::db::schema::scrape_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intents = scrape_intents
+            .filter(source.eq(src))
+            .filter(state.eq(IntentState::Sent))
+            .order(sent_at.asc())
+            .load(&conn)?;
+
+        Ok(intents)
+    }
+
+    /// Moves the intent that is waiting for a result to the final `new_state`.
+    fn finish(
+        &self,
+        intent_id: &Uuid,
+        new_state: IntentState,
+        err: Option<&str>,
+    ) -> Result<Option<ScrapeIntent>, QueryError> {
+        use crate::db::schema::scrape_intents::dsl::*;
+
+        let conn = self.pool.get()?;
+        let intent = diesel::update(
+            scrape_intents
+                .find(intent_id)
+                .filter(state.eq(IntentState::Sent)),
+        )
+        .set((state.eq(new_state), error.eq(err), finished_at.eq(now)))
+        .get_result(&conn)
+        .optional()?;
+
+        Ok(intent)
+    }
+}
@@ -0,0 +1,145 @@
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Bool, Text},
+};
+
+use std::time::Duration;
+
+use crate::db::QueryError;
+
+sql_function!(fn pg_try_advisory_lock(key: BigInt) -> Bool);
+sql_function!(fn pg_advisory_unlock(key: BigInt) -> Bool);
+sql_function!(fn set_config(name: Text, value: Text, is_local: Bool) -> Text);
+
+/// Checks that advisory lock with a single `bigint` key is held by the current session.
+const HELD_QUERY: &str = "select exists (
+    select 1 from pg_locks
+    where locktype = 'advisory' and granted and pid = pg_backend_pid()
+        and objsubid = 1 and ((classid::bigint << 32) | objid::bigint) = $1
+) as held";
+
+/// Session level PostgreSQL advisory lock.
+///
+/// The lock is held by a dedicated connection that is never shared with the pool, so it
+/// can be closed whenever it's unknown whether the lock is still held. PostgreSQL releases
+/// the lock if the connection is closed, so the lock is lost once the server detects that
+/// the holder is gone.
+pub struct AdvisoryLock {
+    url: String,
+    key: i64,
+    conn: Option<PgConnection>,
+    held: bool,
+}
+
+/// Result of `HELD_QUERY`.
+#[derive(QueryableByName)]
+struct Held {
+    #[sql_type = "Bool"]
+    held: bool,
+}
+
+// MARK: impl AdvisoryLock
+
+impl AdvisoryLock {
+    /// Creates new lock that connects to the database at `url` when it's acquired.
+    pub fn new(url: &str, key: i64) -> Self {
+        AdvisoryLock {
+            url: url.to_string(),
+            key,
+            conn: None,
+            held: false,
+        }
+    }
+
+    /// Returns `true` if the lock has been acquired and not released or lost since then.
+    pub fn is_acquired(&self) -> bool {
+        self.held
+    }
+
+    /// Tries to take the lock without waiting or checks that it's still held if it's
+    /// already acquired.
+    ///
+    /// Server is asked to check the connection with TCP keepalive probes every `keepalive`
+    /// so the lock is released soon after the holder becomes unreachable. Returns `true`
+    /// if the lock is held by this instance.
+    pub fn try_acquire(&mut self, keepalive: Duration) -> Result<bool, QueryError> {
+        if self.held {
+            return self.is_held();
+        }
+
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => connect(&self.url, keepalive)?,
+        };
+
+        // connection is dropped on failure, so the lock is released if it's been taken
+        let acquired = diesel::select(pg_try_advisory_lock(self.key)).get_result::<bool>(&conn)?;
+        self.conn = Some(conn);
+        self.held = acquired;
+
+        Ok(acquired)
+    }
+
+    /// Checks that the lock is still held by connection of this instance.
+    ///
+    /// Fails if it's unknown whether the lock is held, e.g. the connection is broken.
+    /// The lock is still considered acquired then, so it should be released to make sure
+    /// it's not held anymore.
+    pub fn is_held(&mut self) -> Result<bool, QueryError> {
+        let conn = match &self.conn {
+            Some(conn) if self.held => conn,
+            _ => return Ok(false),
+        };
+
+        let Held { held } = sql_query(HELD_QUERY)
+            .bind::<BigInt, _>(self.key)
+            .get_result::<Held>(conn)?;
+        self.held = held;
+
+        Ok(held)
+    }
+
+    /// Releases the lock if it's held.
+    ///
+    /// If the lock couldn't be released, the connection is closed so the server releases
+    /// the lock instead.
+    pub fn release(&mut self) -> Result<(), QueryError> {
+        if !self.held {
+            return Ok(());
+        }
+
+        self.held = false;
+        let res = match &self.conn {
+            Some(conn) => diesel::select(pg_advisory_unlock(self.key)).get_result::<bool>(conn),
+            None => return Ok(()),
+        };
+
+        if let Err(e) = res {
+            self.conn = None;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for AdvisoryLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "pg advisory lock {}", self.key)
+    }
+}
+
+/// Opens new connection which server checks with TCP keepalive probes every `keepalive`.
+fn connect(url: &str, keepalive: Duration) -> Result<PgConnection, QueryError> {
+    let conn = PgConnection::establish(url)?;
+    let secs = keepalive.as_secs().max(1).to_string();
+    diesel::select((
+        set_config("tcp_keepalives_idle", secs.as_str(), false),
+        set_config("tcp_keepalives_interval", secs.as_str(), false),
+        set_config("tcp_keepalives_count", "2", false),
+    ))
+    .get_result::<(String, String, String)>(&conn)?;
+
+    Ok(conn)
+}
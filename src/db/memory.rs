@@ -0,0 +1,732 @@
+use chrono::Utc;
+use diesel::result::DatabaseErrorKind;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    db::{
+        entity::{
+            FailedImport, ImportIntent, IndexFile, IndexState, IndexValidators, IntentState,
+            Outcome, Phase, PlanRun, ScrapeIntent, Source,
+        },
+        index::UNPROCESSED,
+        repo::{
+            ImportRepository, IndexRepository, IntentRepository, RunRepository,
+            ScrapeIntentRepository,
+        },
+        QueryError, UnderlyingError,
+    },
+    proto::uuid::Uuid,
+};
+
+/// Storage that keeps scraping plans state in memory.
+///
+/// Used to run plans without database. Mirrors behavior of PostgreSQL access layers.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    state: Arc<Mutex<State>>,
+}
+
+/// Everything stored in memory.
+#[derive(Debug, Default)]
+struct State {
+    /// Index files in order they were received.
+    index_files: Vec<IndexFile>,
+
+    /// Cache validators of the latest index file responses.
+    validators: HashMap<Source, IndexValidators>,
+
+    /// Failed to import titles in order they have failed for the first time.
+    failed_imports: Vec<FailedImport>,
+
+    /// Import intents in order they were sent.
+    import_intents: Vec<ImportIntent>,
+
+    /// Scrape intents in order they were sent.
+    scrape_intents: Vec<ScrapeIntent>,
+
+    /// Plan runs in order they were started.
+    plan_runs: Vec<PlanRun>,
+}
+
+// MARK: impl MemoryStore
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // state is only modified while locked so it's safe to ignore poisoning
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Updates index file with the `id` and returns it's new value.
+    fn update_index<F>(&self, id: &Uuid, f: F) -> Result<IndexFile, QueryError>
+    where
+        F: FnOnce(&mut IndexFile),
+    {
+        let mut state = self.state();
+        let index = state
+            .index_files
+            .iter_mut()
+            .find(|i| &i.id == id)
+            .ok_or(UnderlyingError::NotFound)?;
+
+        f(index);
+        index.updated_at = Utc::now();
+        Ok(index.clone())
+    }
+
+    /// Updates plan run with the `id` and returns it's new value.
+    fn update_run<F>(&self, id: &Uuid, f: F) -> Result<PlanRun, QueryError>
+    where
+        F: FnOnce(&mut PlanRun),
+    {
+        let mut state = self.state();
+        let run = state
+            .plan_runs
+            .iter_mut()
+            .find(|r| &r.id == id)
+            .ok_or(UnderlyingError::NotFound)?;
+
+        f(run);
+        run.updated_at = Utc::now();
+        Ok(run.clone())
+    }
+}
+
+// MARK: impl State
+
+impl State {
+    /// Returns import intent with the `id`.
+    fn import_intent(&mut self, id: &Uuid) -> Result<&mut ImportIntent, UnderlyingError> {
+        self.import_intents
+            .iter_mut()
+            .find(|i| &i.id == id)
+            .ok_or(UnderlyingError::NotFound)
+    }
+
+    /// Returns scrape intent with the `id`.
+    fn scrape_intent(&mut self, id: &Uuid) -> Result<&mut ScrapeIntent, UnderlyingError> {
+        self.scrape_intents
+            .iter_mut()
+            .find(|i| &i.id == id)
+            .ok_or(UnderlyingError::NotFound)
+    }
+
+    /// Moves the import intent that is waiting for a result to the final `new_state`.
+    fn finish_import(
+        &mut self,
+        id: &Uuid,
+        new_state: IntentState,
+        err: Option<&str>,
+    ) -> Result<Option<ImportIntent>, UnderlyingError> {
+        let intent = self.import_intent(id)?;
+        if intent.state != IntentState::Sent {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        intent.state = new_state;
+        intent.error = err.map(str::to_string);
+        intent.finished_at = Some(now);
+        intent.updated_at = now;
+        Ok(Some(intent.clone()))
+    }
+
+    /// Moves the scrape intent that is waiting for a result to the final `new_state`.
+    fn finish_scrape(
+        &mut self,
+        id: &Uuid,
+        new_state: IntentState,
+        err: Option<&str>,
+    ) -> Result<Option<ScrapeIntent>, UnderlyingError> {
+        let intent = self.scrape_intent(id)?;
+        if intent.state != IntentState::Sent {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        intent.state = new_state;
+        intent.error = err.map(str::to_string);
+        intent.finished_at = Some(now);
+        intent.updated_at = now;
+        Ok(Some(intent.clone()))
+    }
+
+    /// Records failed import attempt of titles from the index file.
+    fn record_titles(
+        &mut self,
+        index: &IndexFile,
+        ids: &[i32],
+        err: &str,
+        max_attempts: u32,
+    ) -> Vec<FailedImport> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+
+        let now = Utc::now();
+        let max_attempts = max_attempts as i32;
+        let mut values = vec![];
+        for id in ids {
+            let pos = self
+                .failed_imports
+                .iter()
+                .position(|f| f.source == index.source && f.title_id == id);
+
+            let value = match pos {
+                Some(pos) => {
+                    let value = &mut self.failed_imports[pos];
+                    value.attempts += 1;
+                    value.quarantined |= value.attempts >= max_attempts;
+                    value
+                }
+                None => {
+                    self.failed_imports.push(FailedImport {
+                        source: index.source,
+                        title_id: id,
+                        index_id: index.id.clone(),
+                        attempts: 1,
+                        last_error: None,
+                        quarantined: max_attempts <= 1,
+                        first_failed_at: now,
+                        last_failed_at: now,
+                        created_at: now,
+                        updated_at: now,
+                    });
+                    self.failed_imports.last_mut().unwrap()
+                }
+            };
+
+            value.index_id = index.id.clone();
+            value.last_error = Some(err.to_string());
+            value.last_failed_at = now;
+            value.updated_at = now;
+            values.push(value.clone());
+        }
+
+        values
+    }
+
+    /// Forgets reimported titles of the source and returns how many were forgotten.
+    fn forget_titles(&mut self, src: Source, ids: &[i32]) -> usize {
+        let before = self.failed_imports.len();
+        self.failed_imports
+            .retain(|f| f.source != src || !ids.contains(&f.title_id));
+
+        before - self.failed_imports.len()
+    }
+}
+
+impl IndexRepository for MemoryStore {
+    fn queue(&self, path: &str, src: Source, hash: Option<&str>) -> Result<IndexFile, QueryError> {
+        let mut state = self.state();
+        let pos = match state.index_files.iter().position(|i| i.file_path == path) {
+            Some(pos) => pos,
+            None => {
+                let now = Utc::now();
+                state.index_files.push(IndexFile {
+                    id: Uuid::new(),
+                    source: src,
+                    file_path: path.to_string(),
+                    created_at: now,
+                    updated_at: now,
+                    state: IndexState::Pending,
+                    attempts: 0,
+                    import_started_at: None,
+                    import_failed_at: None,
+                    imported_at: None,
+                    superseded_at: None,
+                    content_hash: hash.map(str::to_string),
+                });
+                state.index_files.len() - 1
+            }
+        };
+
+        if state.index_files[pos].state != IndexState::Pending {
+            return Ok(state.index_files[pos].clone());
+        }
+
+        let imported_hash = state
+            .index_files
+            .iter()
+            .filter(|i| i.source == src && i.state == IndexState::Imported)
+            .max_by_key(|i| i.imported_at)
+            .and_then(|i| i.content_hash.clone());
+
+        let now = Utc::now();
+        if hash.is_some() && imported_hash.as_deref() == hash {
+            let index = &mut state.index_files[pos];
+            index.state = IndexState::Unchanged;
+            index.updated_at = now;
+        }
+
+        let id = state.index_files[pos].id.clone();
+        for index in state.index_files.iter_mut() {
+            if index.source == src && index.id != id && UNPROCESSED.contains(&index.state) {
+                index.state = IndexState::Superseded;
+                index.superseded_at = Some(now);
+                index.updated_at = now;
+            }
+        }
+
+        Ok(state.index_files[pos].clone())
+    }
+
+    fn latest_processed(&self, latest: &IndexFile) -> Result<Option<IndexFile>, QueryError> {
+        if latest.state == IndexState::Imported {
+            return Ok(Some(latest.clone()));
+        }
+
+        let index = self
+            .state()
+            .index_files
+            .iter()
+            .filter(|i| i.source == latest.source && i.state == IndexState::Imported)
+            .max_by_key(|i| i.imported_at)
+            .cloned();
+
+        Ok(index)
+    }
+
+    fn mark_importing(&self, index_file: &IndexFile) -> Result<IndexFile, QueryError> {
+        self.update_index(&index_file.id, |index| {
+            index.state = IndexState::Importing;
+            index.attempts += 1;
+            index.import_started_at = Some(Utc::now());
+        })
+    }
+
+    fn mark_failed(&self, index_id: &Uuid) -> Result<Option<IndexFile>, QueryError> {
+        let mut state = self.state();
+        let index = state
+            .index_files
+            .iter_mut()
+            .find(|i| &i.id == index_id)
+            .ok_or(UnderlyingError::NotFound)?;
+        if index.state != IndexState::Importing {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        index.state = IndexState::Failed;
+        index.import_failed_at = Some(now);
+        index.updated_at = now;
+        Ok(Some(index.clone()))
+    }
+
+    fn mark_processed(&self, index_file: IndexFile) -> Result<IndexFile, QueryError> {
+        self.update_index(&index_file.id, |index| {
+            index.state = IndexState::Imported;
+            index.imported_at = Some(Utc::now());
+        })
+    }
+
+    fn find(&self, index_id: &Uuid) -> Result<IndexFile, QueryError> {
+        self.state()
+            .index_files
+            .iter()
+            .find(|i| &i.id == index_id)
+            .cloned()
+            .ok_or_else(|| UnderlyingError::NotFound.into())
+    }
+
+    fn latest(&self, src: Source) -> Result<Option<IndexFile>, QueryError> {
+        let index = self
+            .state()
+            .index_files
+            .iter()
+            .rev()
+            .find(|i| i.source == src)
+            .cloned();
+
+        Ok(index)
+    }
+
+    fn validators(&self, src: Source) -> Result<Option<IndexValidators>, QueryError> {
+        Ok(self.state().validators.get(&src).cloned())
+    }
+
+    fn save_validators(
+        &self,
+        src: Source,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<IndexValidators, QueryError> {
+        let now = Utc::now();
+        let mut state = self.state();
+        let value = state
+            .validators
+            .entry(src)
+            .or_insert_with(|| IndexValidators {
+                source: src,
+                etag: None,
+                last_modified: None,
+                created_at: now,
+                updated_at: now,
+            });
+
+        value.etag = etag.map(str::to_string);
+        value.last_modified = last_modified.map(str::to_string);
+        value.updated_at = now;
+        Ok(value.clone())
+    }
+}
+
+impl ImportRepository for MemoryStore {
+    fn record(
+        &self,
+        index: &IndexFile,
+        ids: &[i32],
+        err: &str,
+        max_attempts: u32,
+    ) -> Result<Vec<FailedImport>, QueryError> {
+        Ok(self.state().record_titles(index, ids, err, max_attempts))
+    }
+
+    fn eligible(&self, src: Source) -> Result<Vec<FailedImport>, QueryError> {
+        let mut values: Vec<_> = self
+            .state()
+            .failed_imports
+            .iter()
+            .filter(|f| f.source == src && !f.quarantined)
+            .cloned()
+            .collect();
+
+        values.sort_by_key(|f| f.title_id);
+        Ok(values)
+    }
+
+    fn mark_reimported(&self, src: Source, ids: &[i32]) -> Result<usize, QueryError> {
+        Ok(self.state().forget_titles(src, ids))
+    }
+}
+
+impl IntentRepository for MemoryStore {
+    fn create(
+        &self,
+        intent_id: &Uuid,
+        index: &IndexFile,
+        old_index: Option<&IndexFile>,
+        ids: &[i32],
+    ) -> Result<ImportIntent, QueryError> {
+        let mut state = self.state();
+        if state.import_intents.iter().any(|i| &i.id == intent_id) {
+            return Err(UnderlyingError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new(format!("import intent {} already exists", intent_id)),
+            )
+            .into());
+        }
+
+        let now = Utc::now();
+        let intent = ImportIntent {
+            id: intent_id.clone(),
+            source: index.source,
+            index_id: index.id.clone(),
+            old_index_id: old_index.map(|i| i.id.clone()),
+            reimport_ids: ids.to_vec(),
+            skipped_ids: vec![],
+            state: IntentState::Sent,
+            error: None,
+            sent_at: now,
+            finished_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        state.import_intents.push(intent.clone());
+        Ok(intent)
+    }
+
+    fn apply(
+        &self,
+        intent_id: &Uuid,
+        _imported: &[i32],
+        skipped: &[i32],
+        max_attempts: u32,
+    ) -> Result<Option<Vec<FailedImport>>, QueryError> {
+        // imported titles aren't scheduled since scrapers get their tasks from database only
+        // everything is done while the state is locked, so it's applied atomically
+        let mut state = self.state();
+        let intent = state.import_intent(intent_id)?.clone();
+        if intent.state == IntentState::Applied {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        let index = state
+            .index_files
+            .iter_mut()
+            .find(|i| i.id == intent.index_id)
+            .ok_or(UnderlyingError::NotFound)?;
+        index.state = IndexState::Imported;
+        index.imported_at = Some(now);
+        index.updated_at = now;
+        let index = index.clone();
+
+        let (reimported, _) = intent.split_reimported(skipped);
+        state.forget_titles(intent.source, &reimported);
+        let err = format!("skipped by importer in index {}", &index.file_path);
+        let failed = state.record_titles(&index, skipped, &err, max_attempts);
+
+        let intent = state.import_intent(intent_id)?;
+        intent.state = IntentState::Applied;
+        intent.skipped_ids = skipped.to_vec();
+        intent.error = None;
+        intent.finished_at = Some(now);
+        intent.updated_at = now;
+
+        Ok(Some(failed))
+    }
+
+    fn mark_failed(&self, intent_id: &Uuid, err: &str) -> Result<Option<ImportIntent>, QueryError> {
+        let intent = self
+            .state()
+            .finish_import(intent_id, IntentState::Failed, Some(err))?;
+        Ok(intent)
+    }
+
+    fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ImportIntent>, QueryError> {
+        let intent = self
+            .state()
+            .finish_import(intent_id, IntentState::Abandoned, None)?;
+        Ok(intent)
+    }
+
+    fn find(&self, intent_id: &Uuid) -> Result<ImportIntent, QueryError> {
+        Ok(self.state().import_intent(intent_id)?.clone())
+    }
+
+    fn outstanding(&self, src: Source) -> Result<Vec<ImportIntent>, QueryError> {
+        let intents = self
+            .state()
+            .import_intents
+            .iter()
+            .filter(|i| i.source == src && i.state == IntentState::Sent)
+            .cloned()
+            .collect();
+
+        Ok(intents)
+    }
+}
+
+impl ScrapeIntentRepository for MemoryStore {
+    fn create(
+        &self,
+        intent_id: &Uuid,
+        src: Source,
+        endpoint: &str,
+    ) -> Result<ScrapeIntent, QueryError> {
+        let mut state = self.state();
+        if state.scrape_intents.iter().any(|i| &i.id == intent_id) {
+            return Err(UnderlyingError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new(format!("scrape intent {} already exists", intent_id)),
+            )
+            .into());
+        }
+
+        let now = Utc::now();
+        let intent = ScrapeIntent {
+            id: intent_id.clone(),
+            source: src,
+            may_continue: None,
+            state: IntentState::Sent,
+            error: None,
+            sent_at: now,
+            finished_at: None,
+            created_at: now,
+            updated_at: now,
+            endpoint: Some(endpoint.to_string()),
+        };
+
+        state.scrape_intents.push(intent.clone());
+        Ok(intent)
+    }
+
+    fn complete(&self, intent_id: &Uuid, more: bool) -> Result<Option<ScrapeIntent>, QueryError> {
+        let mut state = self.state();
+        let intent = state.scrape_intent(intent_id)?;
+        if intent.state == IntentState::Applied {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        intent.state = IntentState::Applied;
+        intent.may_continue = Some(more);
+        intent.error = None;
+        intent.finished_at = Some(now);
+        intent.updated_at = now;
+        Ok(Some(intent.clone()))
+    }
+
+    fn mark_failed(&self, intent_id: &Uuid, err: &str) -> Result<Option<ScrapeIntent>, QueryError> {
+        let intent = self
+            .state()
+            .finish_scrape(intent_id, IntentState::Failed, Some(err))?;
+        Ok(intent)
+    }
+
+    fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ScrapeIntent>, QueryError> {
+        let intent = self
+            .state()
+            .finish_scrape(intent_id, IntentState::Abandoned, None)?;
+        Ok(intent)
+    }
+
+    fn find(&self, intent_id: &Uuid) -> Result<ScrapeIntent, QueryError> {
+        Ok(self.state().scrape_intent(intent_id)?.clone())
+    }
+
+    fn outstanding(&self, src: Source) -> Result<Vec<ScrapeIntent>, QueryError> {
+        let intents = self
+            .state()
+            .scrape_intents
+            .iter()
+            .filter(|i| i.source == src && i.state == IntentState::Sent)
+            .cloned()
+            .collect();
+
+        Ok(intents)
+    }
+}
+
+impl RunRepository for MemoryStore {
+    fn start(&self, src: Source) -> Result<PlanRun, QueryError> {
+        let now = Utc::now();
+        let run = PlanRun {
+            id: Uuid::new(),
+            source: src,
+            phase: Phase::UpdateIndex,
+            outcome: Outcome::Running,
+            error: None,
+            import_intent_id: None,
+            scrape_intent_id: None,
+            started_at: now,
+            finished_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.state().plan_runs.push(run.clone());
+        Ok(run)
+    }
+
+    fn import_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError> {
+        self.update_run(&run.id, |run| {
+            run.phase = Phase::ImportIndex;
+            run.import_intent_id = Some(intent_id.clone());
+        })
+    }
+
+    fn scrape_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError> {
+        self.update_run(&run.id, |run| {
+            run.phase = Phase::ScrapeData;
+            run.scrape_intent_id = Some(intent_id.clone());
+        })
+    }
+
+    fn finish(
+        &self,
+        run: &PlanRun,
+        result: Outcome,
+        err: Option<&str>,
+    ) -> Result<PlanRun, QueryError> {
+        self.update_run(&run.id, |run| {
+            run.outcome = result;
+            run.error = err.map(str::to_string);
+            run.finished_at = Some(Utc::now());
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStore;
+    use crate::{
+        db::{
+            entity::{IndexState, IntentState, Source},
+            repo::{ImportRepository, IndexRepository, IntentRepository},
+        },
+        proto::uuid::Uuid,
+    };
+
+    #[test]
+    fn test_index_lifecycle() {
+        let store = MemoryStore::new();
+        let first = store.queue("index-1", Source::Anidb, Some("a")).unwrap();
+        let second = store.queue("index-2", Source::Anidb, Some("b")).unwrap();
+        let first = IndexRepository::find(&store, &first.id).unwrap();
+        assert_eq!(first.state, IndexState::Superseded);
+
+        let importing = store.mark_importing(&second).unwrap();
+        assert_eq!(importing.attempts, 1);
+        store.record(&importing, &[42, 43], "skipped", 2).unwrap();
+        store.mark_processed(importing).unwrap();
+
+        let failed = store.eligible(Source::Anidb).unwrap();
+        assert_eq!(failed.len(), 2);
+        assert!(store.eligible(Source::Mal).unwrap().is_empty());
+
+        assert_eq!(store.mark_reimported(Source::Anidb, &[42]).unwrap(), 1);
+        let failed = store.record(&second, &[43], "skipped", 2).unwrap();
+        assert!(failed[0].quarantined);
+        assert!(store.eligible(Source::Anidb).unwrap().is_empty());
+
+        let same = store.queue("index-3", Source::Anidb, Some("b")).unwrap();
+        assert_eq!(same.state, IndexState::Unchanged);
+        assert_eq!(store.latest(Source::Anidb).unwrap().unwrap().id, same.id);
+    }
+
+    #[test]
+    fn test_only_importing_index_is_failed() {
+        let store = MemoryStore::new();
+        let index = store.queue("index-1", Source::Anidb, None).unwrap();
+        let failed = IndexRepository::mark_failed(&store, &index.id).unwrap();
+        assert!(failed.is_none());
+
+        store.mark_importing(&index).unwrap();
+        let failed = IndexRepository::mark_failed(&store, &index.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(failed.state, IndexState::Failed);
+        assert_eq!(failed.attempts, 1);
+    }
+
+    #[test]
+    fn test_import_result_is_applied_once() {
+        let store = MemoryStore::new();
+        let index = store.queue("index-1", Source::Anidb, None).unwrap();
+        store.record(&index, &[42, 43], "skipped", 3).unwrap();
+
+        let intent_id = Uuid::new();
+        let intent = store.create(&intent_id, &index, None, &[42, 43]).unwrap();
+        assert_eq!(intent.state, IntentState::Sent);
+        assert!(store.create(&intent_id, &index, None, &[]).is_err());
+
+        let failed = store.apply(&intent_id, &[], &[43, 44], 3).unwrap().unwrap();
+        assert_eq!(failed.len(), 2);
+        assert!(store
+            .apply(&intent_id, &[], &[43, 44], 3)
+            .unwrap()
+            .is_none());
+        let late = IntentRepository::mark_failed(&store, &intent_id, "late failure");
+        assert!(late.unwrap().is_none());
+
+        let titles = store.eligible(Source::Anidb).unwrap();
+        let attempts: Vec<_> = titles.iter().map(|f| (f.title_id, f.attempts)).collect();
+        assert_eq!(attempts, vec![(43, 2), (44, 1)]);
+
+        let intent = IntentRepository::find(&store, &intent_id).unwrap();
+        assert_eq!(intent.state, IntentState::Applied);
+        assert_eq!(intent.skipped_ids, vec![43, 44]);
+        let index = IndexRepository::find(&store, &index.id).unwrap();
+        assert_eq!(index.state, IndexState::Imported);
+    }
+}
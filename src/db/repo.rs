@@ -0,0 +1,362 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+    db::{
+        entity::{
+            FailedImport, ImportIntent, IndexFile, IndexValidators, Outcome, PlanRun, ScrapeIntent,
+            Source,
+        },
+        import::FailedImports,
+        index::IndexFiles,
+        intents::{ImportIntents, ScrapeIntents},
+        memory::MemoryStore,
+        runs::PlanRuns,
+        ConnectionPool, QueryError,
+    },
+    proto::uuid::Uuid,
+};
+
+/// Storage of anime index files used by scraping plans.
+pub trait IndexRepository: Debug + Send + Sync {
+    /// Saves new index file and marks older not imported index files as superseded.
+    fn queue(&self, path: &str, src: Source, hash: Option<&str>) -> Result<IndexFile, QueryError>;
+
+    /// Returns last imported index file of the same source as `latest`.
+    fn latest_processed(&self, latest: &IndexFile) -> Result<Option<IndexFile>, QueryError>;
+
+    /// Records new import attempt of the index file.
+    fn mark_importing(&self, index_file: &IndexFile) -> Result<IndexFile, QueryError>;
+
+    /// Records failed import attempt of the index file if it's being imported.
+    fn mark_failed(&self, index_id: &Uuid) -> Result<Option<IndexFile>, QueryError>;
+
+    /// Marks index file as imported.
+    fn mark_processed(&self, index_file: IndexFile) -> Result<IndexFile, QueryError>;
+
+    /// Returns index file with the `index_id`.
+    fn find(&self, index_id: &Uuid) -> Result<IndexFile, QueryError>;
+
+    /// Returns most recently received index file of the source.
+    fn latest(&self, src: Source) -> Result<Option<IndexFile>, QueryError>;
+
+    /// Returns cache validators of the last latest index file response for the source.
+    fn validators(&self, src: Source) -> Result<Option<IndexValidators>, QueryError>;
+
+    /// Saves cache validators of the latest index file response for the source.
+    fn save_validators(
+        &self,
+        src: Source,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<IndexValidators, QueryError>;
+}
+
+/// Storage of anime titles that failed to import.
+pub trait ImportRepository: Debug + Send + Sync {
+    /// Records failed import attempt of titles from the index file and quarantines
+    /// ones that have failed `max_attempts` times.
+    fn record(
+        &self,
+        index: &IndexFile,
+        ids: &[i32],
+        err: &str,
+        max_attempts: u32,
+    ) -> Result<Vec<FailedImport>, QueryError>;
+
+    /// Returns titles of the source that should be reimported.
+    fn eligible(&self, src: Source) -> Result<Vec<FailedImport>, QueryError>;
+
+    /// Forgets titles of the source that have been successfully reimported.
+    fn mark_reimported(&self, src: Source, ids: &[i32]) -> Result<usize, QueryError>;
+}
+
+/// Storage of intents sent to importer service.
+pub trait IntentRepository: Debug + Send + Sync {
+    /// Records import intent of the index file before it's sent to importer service.
+    fn create(
+        &self,
+        intent_id: &Uuid,
+        index: &IndexFile,
+        old_index: Option<&IndexFile>,
+        ids: &[i32],
+    ) -> Result<ImportIntent, QueryError>;
+
+    /// Atomically applies result of the import intent and returns titles that failed
+    /// to import, or `None` if the result has already been applied.
+    fn apply(
+        &self,
+        intent_id: &Uuid,
+        imported: &[i32],
+        skipped: &[i32],
+        max_attempts: u32,
+    ) -> Result<Option<Vec<FailedImport>>, QueryError>;
+
+    /// Records that importer service has failed to process the intent.
+    fn mark_failed(&self, intent_id: &Uuid, err: &str) -> Result<Option<ImportIntent>, QueryError>;
+
+    /// Records that the intent has never completed and won't be sent again.
+    fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ImportIntent>, QueryError>;
+
+    /// Returns import intent with the `intent_id`.
+    fn find(&self, intent_id: &Uuid) -> Result<ImportIntent, QueryError>;
+
+    /// Returns import intents of the source that have been sent but never completed.
+    fn outstanding(&self, src: Source) -> Result<Vec<ImportIntent>, QueryError>;
+}
+
+/// Storage of intents sent to scraping service.
+pub trait ScrapeIntentRepository: Debug + Send + Sync {
+    /// Records scrape intent of the source before it's sent to the scraper at `endpoint`.
+    fn create(
+        &self,
+        intent_id: &Uuid,
+        src: Source,
+        endpoint: &str,
+    ) -> Result<ScrapeIntent, QueryError>;
+
+    /// Records result of the scrape intent, or returns `None` if it's already recorded.
+    fn complete(&self, intent_id: &Uuid, more: bool) -> Result<Option<ScrapeIntent>, QueryError>;
+
+    /// Records that scraping service has failed to process the intent.
+    fn mark_failed(&self, intent_id: &Uuid, err: &str) -> Result<Option<ScrapeIntent>, QueryError>;
+
+    /// Records that the intent has never completed and won't be sent again.
+    fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ScrapeIntent>, QueryError>;
+
+    /// Returns scrape intent with the `intent_id`.
+    fn find(&self, intent_id: &Uuid) -> Result<ScrapeIntent, QueryError>;
+
+    /// Returns scrape intents of the source that have been sent but never completed.
+    fn outstanding(&self, src: Source) -> Result<Vec<ScrapeIntent>, QueryError>;
+}
+
+/// Storage of scraping plan execution history.
+pub trait RunRepository: Debug + Send + Sync {
+    /// Records new plan run of the source.
+    fn start(&self, src: Source) -> Result<PlanRun, QueryError>;
+
+    /// Records that the run has started importing with `intent_id`.
+    fn import_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError>;
+
+    /// Records that the run has started scraping with `intent_id`.
+    fn scrape_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError>;
+
+    /// Records outcome of the run.
+    fn finish(
+        &self,
+        run: &PlanRun,
+        result: Outcome,
+        err: Option<&str>,
+    ) -> Result<PlanRun, QueryError>;
+}
+
+/// Storages used by scraping plans.
+#[derive(Debug, Clone)]
+pub struct Repositories {
+    /// Anime index files.
+    pub index_files: Arc<dyn IndexRepository>,
+
+    /// Failed to import anime titles.
+    pub failed_imports: Arc<dyn ImportRepository>,
+
+    /// Intents sent to importer service.
+    pub import_intents: Arc<dyn IntentRepository>,
+
+    /// Intents sent to scraping service.
+    pub scrape_intents: Arc<dyn ScrapeIntentRepository>,
+
+    /// Plan execution history.
+    pub plan_runs: Arc<dyn RunRepository>,
+}
+
+// MARK: impl Repositories
+
+impl Repositories {
+    /// Returns repositories backed by PostgreSQL.
+    pub fn postgres(pool: ConnectionPool) -> Self {
+        Repositories {
+            index_files: Arc::new(IndexFiles::new(pool.clone())),
+            failed_imports: Arc::new(FailedImports::new(pool.clone())),
+            import_intents: Arc::new(ImportIntents::new(pool.clone())),
+            scrape_intents: Arc::new(ScrapeIntents::new(pool.clone())),
+            plan_runs: Arc::new(PlanRuns::new(pool)),
+        }
+    }
+
+    /// Returns repositories that keep everything in memory and forget it on exit.
+    pub fn memory() -> Self {
+        let store = MemoryStore::new();
+        Repositories {
+            index_files: Arc::new(store.clone()),
+            failed_imports: Arc::new(store.clone()),
+            import_intents: Arc::new(store.clone()),
+            scrape_intents: Arc::new(store.clone()),
+            plan_runs: Arc::new(store),
+        }
+    }
+}
+
+// MARK: impl IndexFiles
+
+impl IndexRepository for IndexFiles {
+    fn queue(&self, path: &str, src: Source, hash: Option<&str>) -> Result<IndexFile, QueryError> {
+        IndexFiles::queue(self, path, src, hash)
+    }
+
+    fn latest_processed(&self, latest: &IndexFile) -> Result<Option<IndexFile>, QueryError> {
+        IndexFiles::latest_processed(self, latest)
+    }
+
+    fn mark_importing(&self, index_file: &IndexFile) -> Result<IndexFile, QueryError> {
+        IndexFiles::mark_importing(self, index_file)
+    }
+
+    fn mark_failed(&self, index_id: &Uuid) -> Result<Option<IndexFile>, QueryError> {
+        IndexFiles::mark_failed(self, index_id)
+    }
+
+    fn mark_processed(&self, index_file: IndexFile) -> Result<IndexFile, QueryError> {
+        IndexFiles::mark_processed(self, index_file)
+    }
+
+    fn find(&self, index_id: &Uuid) -> Result<IndexFile, QueryError> {
+        IndexFiles::find(self, index_id)
+    }
+
+    fn latest(&self, src: Source) -> Result<Option<IndexFile>, QueryError> {
+        IndexFiles::latest(self, src)
+    }
+
+    fn validators(&self, src: Source) -> Result<Option<IndexValidators>, QueryError> {
+        IndexFiles::validators(self, src)
+    }
+
+    fn save_validators(
+        &self,
+        src: Source,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<IndexValidators, QueryError> {
+        IndexFiles::save_validators(self, src, etag, last_modified)
+    }
+}
+
+// MARK: impl FailedImports
+
+impl ImportRepository for FailedImports {
+    fn record(
+        &self,
+        index: &IndexFile,
+        ids: &[i32],
+        err: &str,
+        max_attempts: u32,
+    ) -> Result<Vec<FailedImport>, QueryError> {
+        FailedImports::record(self, index, ids, err, max_attempts)
+    }
+
+    fn eligible(&self, src: Source) -> Result<Vec<FailedImport>, QueryError> {
+        FailedImports::eligible(self, src)
+    }
+
+    fn mark_reimported(&self, src: Source, ids: &[i32]) -> Result<usize, QueryError> {
+        FailedImports::mark_reimported(self, src, ids)
+    }
+}
+
+// MARK: impl ImportIntents
+
+impl IntentRepository for ImportIntents {
+    fn create(
+        &self,
+        intent_id: &Uuid,
+        index: &IndexFile,
+        old_index: Option<&IndexFile>,
+        ids: &[i32],
+    ) -> Result<ImportIntent, QueryError> {
+        ImportIntents::create(self, intent_id, index, old_index, ids)
+    }
+
+    fn apply(
+        &self,
+        intent_id: &Uuid,
+        imported: &[i32],
+        skipped: &[i32],
+        max_attempts: u32,
+    ) -> Result<Option<Vec<FailedImport>>, QueryError> {
+        ImportIntents::apply(self, intent_id, imported, skipped, max_attempts)
+    }
+
+    fn mark_failed(&self, intent_id: &Uuid, err: &str) -> Result<Option<ImportIntent>, QueryError> {
+        ImportIntents::mark_failed(self, intent_id, err)
+    }
+
+    fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ImportIntent>, QueryError> {
+        ImportIntents::mark_abandoned(self, intent_id)
+    }
+
+    fn find(&self, intent_id: &Uuid) -> Result<ImportIntent, QueryError> {
+        ImportIntents::find(self, intent_id)
+    }
+
+    fn outstanding(&self, src: Source) -> Result<Vec<ImportIntent>, QueryError> {
+        ImportIntents::outstanding(self, src)
+    }
+}
+
+// MARK: impl ScrapeIntents
+
+impl ScrapeIntentRepository for ScrapeIntents {
+    fn create(
+        &self,
+        intent_id: &Uuid,
+        src: Source,
+        endpoint: &str,
+    ) -> Result<ScrapeIntent, QueryError> {
+        ScrapeIntents::create(self, intent_id, src, endpoint)
+    }
+
+    fn complete(&self, intent_id: &Uuid, more: bool) -> Result<Option<ScrapeIntent>, QueryError> {
+        ScrapeIntents::complete(self, intent_id, more)
+    }
+
+    fn mark_failed(&self, intent_id: &Uuid, err: &str) -> Result<Option<ScrapeIntent>, QueryError> {
+        ScrapeIntents::mark_failed(self, intent_id, err)
+    }
+
+    fn mark_abandoned(&self, intent_id: &Uuid) -> Result<Option<ScrapeIntent>, QueryError> {
+        ScrapeIntents::mark_abandoned(self, intent_id)
+    }
+
+    fn find(&self, intent_id: &Uuid) -> Result<ScrapeIntent, QueryError> {
+        ScrapeIntents::find(self, intent_id)
+    }
+
+    fn outstanding(&self, src: Source) -> Result<Vec<ScrapeIntent>, QueryError> {
+        ScrapeIntents::outstanding(self, src)
+    }
+}
+
+// MARK: impl PlanRuns
+
+impl RunRepository for PlanRuns {
+    fn start(&self, src: Source) -> Result<PlanRun, QueryError> {
+        PlanRuns::start(self, src)
+    }
+
+    fn import_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError> {
+        PlanRuns::import_started(self, run, intent_id)
+    }
+
+    fn scrape_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError> {
+        PlanRuns::scrape_started(self, run, intent_id)
+    }
+
+    fn finish(
+        &self,
+        run: &PlanRun,
+        result: Outcome,
+        err: Option<&str>,
+    ) -> Result<PlanRun, QueryError> {
+        PlanRuns::finish(self, run, result, err)
+    }
+}
@@ -0,0 +1,119 @@
+use diesel::{dsl::now, prelude::*};
+
+use crate::{
+    db::{
+        entity::{Outcome, Phase, PlanRun, Source},
+        ConnectionPool, QueryError,
+    },
+    proto::uuid::Uuid,
+};
+
+#[derive(Debug, Clone)]
+pub struct PlanRuns {
+    pool: ConnectionPool,
+}
+
+impl PlanRuns {
+    pub fn new(pool: ConnectionPool) -> Self {
+        PlanRuns { pool }
+    }
+
+    pub fn start(&self, src: Source) -> Result<PlanRun, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let run = diesel::insert_into(plan_runs)
+            .values((
+                source.eq(src),
+                phase.eq(Phase::UpdateIndex),
+                outcome.eq(Outcome::Running),
+            ))
+            .get_result(&conn)?;
+
+        Ok(run)
+    }
+
+    pub fn import_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let run = diesel::update(run)
+            .set((phase.eq(Phase::ImportIndex), import_intent_id.eq(intent_id)))
+            .get_result(&conn)?;
+
+        Ok(run)
+    }
+
+    pub fn scrape_started(&self, run: &PlanRun, intent_id: &Uuid) -> Result<PlanRun, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let run = diesel::update(run)
+            .set((phase.eq(Phase::ScrapeData), scrape_intent_id.eq(intent_id)))
+            .get_result(&conn)?;
+
+        Ok(run)
+    }
+
+    pub fn finish(
+        &self,
+        run: &PlanRun,
+        result: Outcome,
+        err: Option<&str>,
+    ) -> Result<PlanRun, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let run = diesel::update(run)
+            .set((outcome.eq(result), error.eq(err), finished_at.eq(now)))
+            .get_result(&conn)?;
+
+        Ok(run)
+    }
+
+    pub fn interrupt_running(&self, src: Source, reason: &str) -> Result<usize, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let count = diesel::update(
+            plan_runs
+                .filter(source.eq(src))
+                .filter(outcome.eq(Outcome::Running)),
+        )
+        .set((
+            outcome.eq(Outcome::Interrupted),
+            error.eq(reason),
+            finished_at.eq(now),
+        ))
+        .execute(&conn)?;
+
+        Ok(count)
+    }
+
+    pub fn latest_succeeded(&self, src: Source) -> Result<Option<PlanRun>, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let run = plan_runs
+            .filter(source.eq(src))
+            .filter(outcome.eq(Outcome::Succeeded))
+            .order(started_at.desc())
+            .first(&conn)
+            .optional()?;
+
+        Ok(run)
+    }
+
+    pub fn list(&self, src: Option<Source>, limit: i64) -> Result<Vec<PlanRun>, QueryError> {
+        use crate::db::schema::plan_runs::dsl::*;
+
+        let conn = self.pool.get()?;
+        let mut query = plan_runs.order(started_at.desc()).limit(limit).into_boxed();
+        if let Some(src) = src {
+            query = query.filter(source.eq(src));
+        }
+
+        let runs = query.load(&conn)?;
+        Ok(runs)
+    }
+}
@@ -1,9 +1,30 @@
 table! {
-    failed_imports (id) {
+    failed_imports (source, title_id) {
+        source -> Int4,
+        title_id -> Int4,
+        index_id -> Uuid,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        quarantined -> Bool,
+        first_failed_at -> Timestamptz,
+        last_failed_at -> Timestamptz,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    import_intents (id) {
         id -> Uuid,
+        source -> Int4,
         index_id -> Uuid,
-        title_ids -> Array<Int4>,
-        reimported -> Bool,
+        old_index_id -> Nullable<Uuid>,
+        reimport_ids -> Array<Int4>,
+        skipped_ids -> Array<Int4>,
+        state -> Int4,
+        error -> Nullable<Text>,
+        sent_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
@@ -14,15 +35,119 @@ table! {
         id -> Uuid,
         source -> Int4,
         file_path -> Text,
-        pending -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        state -> Int4,
+        attempts -> Int4,
+        import_started_at -> Nullable<Timestamptz>,
+        import_failed_at -> Nullable<Timestamptz>,
+        imported_at -> Nullable<Timestamptz>,
+        superseded_at -> Nullable<Timestamptz>,
+        content_hash -> Nullable<Text>,
+    }
+}
+
+table! {
+    index_validators (source) {
+        source -> Int4,
+        etag -> Nullable<Text>,
+        last_modified -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    plan_runs (id) {
+        id -> Uuid,
+        source -> Int4,
+        phase -> Int4,
+        outcome -> Int4,
+        error -> Nullable<Text>,
+        import_intent_id -> Nullable<Uuid>,
+        scrape_intent_id -> Nullable<Uuid>,
+        started_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    plan_states (source) {
+        source -> Int4,
+        consecutive_failures -> Int4,
+        degraded -> Bool,
+        last_error -> Nullable<Text>,
+        degraded_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        paused -> Bool,
+    }
+}
+
+table! {
+    scrape_intents (id) {
+        id -> Uuid,
+        source -> Int4,
+        may_continue -> Nullable<Bool>,
+        state -> Int4,
+        error -> Nullable<Text>,
+        sent_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        endpoint -> Nullable<Text>,
+    }
+}
+
+table! {
+    scrape_jobs (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        schedule_id -> Uuid,
+        anime_id -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    scrape_schedules (id) {
+        id -> Uuid,
+        source -> Int4,
+        anime_id -> Int4,
+        state -> Int4,
+        next_update_at -> Timestamptz,
+        update_count -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    scrape_tasks (id) {
+        id -> Uuid,
+        source -> Int4,
+        finished -> Bool,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
 }
 
 joinable!(failed_imports -> index_files (index_id));
+joinable!(scrape_jobs -> scrape_schedules (schedule_id));
+joinable!(scrape_jobs -> scrape_tasks (task_id));
 
 allow_tables_to_appear_in_same_query!(
     failed_imports,
+    import_intents,
     index_files,
+    index_validators,
+    plan_runs,
+    plan_states,
+    scrape_intents,
+    scrape_jobs,
+    scrape_schedules,
+    scrape_tasks,
 );
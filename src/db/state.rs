@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use diesel::{dsl::now, prelude::*};
+
+use crate::db::{
+    entity::{PlanState, Source},
+    ConnectionPool, QueryError,
+};
+
+#[derive(Debug, Clone)]
+pub struct PlanStates {
+    pool: ConnectionPool,
+}
+
+impl PlanStates {
+    pub fn new(pool: ConnectionPool) -> Self {
+        PlanStates { pool }
+    }
+
+    pub fn get(&self, src: Source) -> Result<PlanState, QueryError> {
+        use crate::db::schema::plan_states::dsl::*;
+
+        let conn = self.pool.get()?;
+        let state = diesel::insert_into(plan_states)
+            .values(source.eq(src))
+            .on_conflict(source)
+            .do_update()
+            .set(source.eq(src))
+            .get_result(&conn)?;
+
+        Ok(state)
+    }
+
+    pub fn failed(&self, src: Source, failures: i32, err: &str) -> Result<PlanState, QueryError> {
+        use crate::db::schema::plan_states::dsl::*;
+
+        let conn = self.pool.get()?;
+        let state = diesel::insert_into(plan_states)
+            .values((
+                source.eq(src),
+                consecutive_failures.eq(failures),
+                last_error.eq(err),
+            ))
+            .on_conflict(source)
+            .do_update()
+            .set((consecutive_failures.eq(failures), last_error.eq(err)))
+            .get_result(&conn)?;
+
+        Ok(state)
+    }
+
+    pub fn degrade(&self, src: Source) -> Result<PlanState, QueryError> {
+        use crate::db::schema::plan_states::dsl::*;
+
+        let conn = self.pool.get()?;
+        diesel::update(plan_states.find(src).filter(degraded.eq(false)))
+            .set((degraded.eq(true), degraded_at.eq(now)))
+            .execute(&conn)?;
+
+        let state = plan_states.find(src).first(&conn)?;
+        Ok(state)
+    }
+
+    pub fn recover(&self, src: Source) -> Result<PlanState, QueryError> {
+        use crate::db::schema::plan_states::dsl::*;
+
+        let conn = self.pool.get()?;
+        let state = diesel::insert_into(plan_states)
+            .values(source.eq(src))
+            .on_conflict(source)
+            .do_update()
+            .set((
+                consecutive_failures.eq(0),
+                degraded.eq(false),
+                last_error.eq(None::<String>),
+                degraded_at.eq(None::<DateTime<Utc>>),
+            ))
+            .get_result(&conn)?;
+
+        Ok(state)
+    }
+
+    pub fn set_paused(&self, src: Source, value: bool) -> Result<PlanState, QueryError> {
+        use crate::db::schema::plan_states::dsl::*;
+
+        let conn = self.pool.get()?;
+        let state = diesel::insert_into(plan_states)
+            .values((source.eq(src), paused.eq(value)))
+            .on_conflict(source)
+            .do_update()
+            .set(paused.eq(value))
+            .get_result(&conn)?;
+
+        Ok(state)
+    }
+}
@@ -0,0 +1,215 @@
+use chrono::{DateTime, Utc};
+use diesel::{dsl::now, prelude::*, PgConnection};
+
+use std::time::Duration;
+
+use crate::{
+    db::{
+        entity::{ScheduleState, ScrapeJob, ScrapeSchedule, ScrapeTask, Source},
+        ConnectionPool, QueryError, UnderlyingError,
+    },
+    proto::uuid::Uuid,
+};
+
+#[derive(Debug, Clone)]
+pub struct ScrapeTasks {
+    pool: ConnectionPool,
+}
+
+impl ScrapeTasks {
+    pub fn new(pool: ConnectionPool) -> Self {
+        ScrapeTasks { pool }
+    }
+
+    /// Creates scraping task with anime titles of the source that are due for scraping.
+    ///
+    /// Titles of unfinished tasks that haven't made progress for `lease` are handed out
+    /// again. If there's nothing to scrape, returned task has no jobs and isn't stored.
+    pub fn create(
+        &self,
+        src: Source,
+        limit: i32,
+        lease: Duration,
+    ) -> Result<(ScrapeTask, Vec<ScrapeJob>), QueryError> {
+        use crate::db::schema::{scrape_jobs, scrape_schedules, scrape_tasks};
+
+        let conn = self.pool.get()?;
+        let created = conn.transaction::<_, UnderlyingError, _>(|| {
+            reclaim_expired(&conn, src, lease)?;
+
+            let due = scrape_schedules::table
+                .filter(scrape_schedules::source.eq(src))
+                .filter(scrape_schedules::state.eq(ScheduleState::Pending))
+                .filter(scrape_schedules::next_update_at.le(Utc::now()))
+                .order((
+                    scrape_schedules::update_count.asc(),
+                    scrape_schedules::next_update_at.asc(),
+                ))
+                .limit(i64::from(limit))
+                .for_update()
+                .skip_locked()
+                .load::<ScrapeSchedule>(&conn)?;
+
+            if due.is_empty() {
+                let created_at = Utc::now();
+                let task = ScrapeTask {
+                    id: Uuid::new(),
+                    source: src,
+                    finished: true,
+                    created_at,
+                    updated_at: created_at,
+                };
+
+                return Ok((task, vec![]));
+            }
+
+            let task: ScrapeTask = diesel::insert_into(scrape_tasks::table)
+                .values(scrape_tasks::source.eq(src))
+                .get_result(&conn)?;
+
+            let ids: Vec<_> = due.iter().map(|s| s.id.clone()).collect();
+            diesel::update(scrape_schedules::table.filter(scrape_schedules::id.eq_any(ids)))
+                .set(scrape_schedules::state.eq(ScheduleState::Processing))
+                .execute(&conn)?;
+
+            let jobs: Vec<_> = due
+                .iter()
+                .map(|s| {
+                    (
+                        scrape_jobs::task_id.eq(&task.id),
+                        scrape_jobs::schedule_id.eq(&s.id),
+                        scrape_jobs::anime_id.eq(s.anime_id),
+                    )
+                })
+                .collect();
+
+            let jobs = diesel::insert_into(scrape_jobs::table)
+                .values(&jobs)
+                .get_results(&conn)?;
+
+            Ok((task, jobs))
+        })?;
+
+        Ok(created)
+    }
+
+    /// Completes the job and schedules next update of it's anime title.
+    ///
+    /// Task's lease is renewed, so the rest of it's jobs stay reserved for the scraper.
+    pub fn complete_job(
+        &self,
+        task: &Uuid,
+        job: &Uuid,
+        next_update: DateTime<Utc>,
+    ) -> Result<ScrapeSchedule, QueryError> {
+        use crate::db::schema::{scrape_jobs, scrape_schedules, scrape_tasks};
+
+        let conn = self.pool.get()?;
+        let schedule = conn.transaction::<_, UnderlyingError, _>(|| {
+            let job: ScrapeJob = diesel::delete(
+                scrape_jobs::table
+                    .find(job)
+                    .filter(scrape_jobs::task_id.eq(task)),
+            )
+            .get_result(&conn)?;
+
+            diesel::update(scrape_tasks::table.find(task))
+                .set(scrape_tasks::updated_at.eq(now))
+                .execute(&conn)?;
+
+            diesel::update(scrape_schedules::table.find(&job.schedule_id))
+                .set((
+                    scrape_schedules::state.eq(ScheduleState::Pending),
+                    scrape_schedules::next_update_at.eq(next_update),
+                    scrape_schedules::update_count.eq(scrape_schedules::update_count + 1),
+                ))
+                .get_result(&conn)
+        })?;
+
+        Ok(schedule)
+    }
+
+    /// Finishes the task and hands out titles it hasn't scraped again.
+    ///
+    /// Returns `None` if the task isn't stored, e.g. it had no jobs.
+    pub fn finish(&self, task: &Uuid) -> Result<Option<ScrapeTask>, QueryError> {
+        use crate::db::schema::scrape_tasks;
+
+        let conn = self.pool.get()?;
+        let task = conn.transaction::<_, UnderlyingError, _>(|| {
+            release_jobs(&conn, vec![task.clone()])?;
+            diesel::update(scrape_tasks::table.find(task))
+                .set(scrape_tasks::finished.eq(true))
+                .get_result(&conn)
+                .optional()
+        })?;
+
+        Ok(task)
+    }
+}
+
+/// Finishes tasks of the source that haven't made progress for `lease`.
+fn reclaim_expired(conn: &PgConnection, src: Source, lease: Duration) -> QueryResult<()> {
+    use crate::db::schema::scrape_tasks::dsl::*;
+
+    let expired_at = match chrono::Duration::from_std(lease)
+        .ok()
+        .and_then(|lease| Utc::now().checked_sub_signed(lease))
+    {
+        Some(expired_at) => expired_at,
+        None => return Ok(()),
+    };
+
+    let expired: Vec<Uuid> = diesel::update(
+        scrape_tasks
+            .filter(source.eq(src))
+            .filter(finished.eq(false))
+            .filter(updated_at.lt(expired_at)),
+    )
+    .set(finished.eq(true))
+    .returning(id)
+    .get_results(conn)?;
+
+    release_jobs(conn, expired)?;
+    Ok(())
+}
+
+/// Deletes jobs of the tasks and makes their titles available for scraping again.
+fn release_jobs(conn: &PgConnection, tasks: Vec<Uuid>) -> QueryResult<usize> {
+    use crate::db::schema::{scrape_jobs, scrape_schedules};
+
+    if tasks.is_empty() {
+        return Ok(0);
+    }
+
+    let released: Vec<Uuid> =
+        diesel::delete(scrape_jobs::table.filter(scrape_jobs::task_id.eq_any(tasks)))
+            .returning(scrape_jobs::schedule_id)
+            .get_results(conn)?;
+
+    diesel::update(scrape_schedules::table.filter(scrape_schedules::id.eq_any(released)))
+        .set(scrape_schedules::state.eq(ScheduleState::Pending))
+        .execute(conn)
+}
+
+/// Schedules anime titles of the source for scraping as soon as possible.
+///
+/// Titles that are already scheduled keep their schedule. Returns number of new schedules.
+pub(crate) fn schedule(conn: &PgConnection, src: Source, ids: &[i32]) -> QueryResult<usize> {
+    use crate::db::schema::scrape_schedules::dsl::*;
+
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let new_schedules: Vec<_> = ids
+        .iter()
+        .map(|&id| (source.eq(src), anime_id.eq(id)))
+        .collect();
+
+    diesel::insert_into(scrape_schedules)
+        .values(&new_schedules)
+        .on_conflict((source, anime_id))
+        .do_nothing()
+        .execute(conn)
+}
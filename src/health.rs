@@ -0,0 +1,240 @@
+use hyper::Uri;
+use tokio::{net::TcpStream, task, time};
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    db::{entity::Source, ConnectionPool},
+    settings,
+};
+
+/// Tracks progress of scraping plans to detect stuck ones.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    /// Time since which plans of different sources are running.
+    busy_since: Arc<Mutex<HashMap<Source, Instant>>>,
+
+    /// Sources which plans have stopped before shutdown.
+    dead: Arc<Mutex<HashSet<Source>>>,
+
+    /// How long a plan may run before it's considered stuck.
+    stall_timeout: Duration,
+}
+
+/// Marks plan as dead when dropped unless the plan has been stopped normally.
+///
+/// The guard is dropped on panic too, so a crashed plan fails liveness check.
+#[derive(Debug)]
+pub struct PlanGuard {
+    /// Tracker to report to.
+    liveness: Liveness,
+
+    /// Anime source of the plan.
+    source: Source,
+
+    /// Whether the plan has been stopped normally.
+    stopped: bool,
+}
+
+/// Checks whether the app is able to do it's work.
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    /// Database connection pool to check.
+    pool: ConnectionPool,
+
+    /// Addresses of external services to check.
+    endpoints: Vec<String>,
+
+    /// How long to wait for an external service to accept connection.
+    check_timeout: Duration,
+}
+
+// MARK: impl Liveness
+
+impl Liveness {
+    /// Creates new tracker instance.
+    pub fn new(settings: &settings::Health) -> Self {
+        Liveness {
+            busy_since: Arc::new(Mutex::new(HashMap::new())),
+            dead: Arc::new(Mutex::new(HashSet::new())),
+            stall_timeout: settings.stall_timeout(),
+        }
+    }
+
+    /// Records that plan of the `source` has started running.
+    pub fn started(&self, source: Source) {
+        self.lock().insert(source, Instant::now());
+    }
+
+    /// Records that plan of the `source` has finished running.
+    pub fn finished(&self, source: Source) {
+        self.lock().remove(&source);
+    }
+
+    /// Returns guard that should be kept while plan of the `source` is running.
+    pub fn guard(&self, source: Source) -> PlanGuard {
+        PlanGuard {
+            liveness: self.clone(),
+            source,
+            stopped: false,
+        }
+    }
+
+    /// Returns sources which plans are running longer than allowed.
+    pub fn stalled(&self) -> Vec<Source> {
+        self.lock()
+            .iter()
+            .filter(|(_, since)| since.elapsed() > self.stall_timeout)
+            .map(|(source, _)| *source)
+            .collect()
+    }
+
+    /// Returns sources which plans have crashed or stopped before shutdown.
+    pub fn dead(&self) -> Vec<Source> {
+        self.dead_lock().iter().copied().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Source, Instant>> {
+        // plans don't panic while holding the lock so it's safe to ignore poisoning
+        self.busy_since.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn dead_lock(&self) -> MutexGuard<'_, HashSet<Source>> {
+        self.dead.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// MARK: impl PlanGuard
+
+impl PlanGuard {
+    /// Records that the plan has been stopped on shutdown.
+    pub fn stopped(mut self) {
+        self.stopped = true;
+    }
+}
+
+impl Drop for PlanGuard {
+    fn drop(&mut self) {
+        if !self.stopped {
+            self.liveness.finished(self.source);
+            self.liveness.dead_lock().insert(self.source);
+        }
+    }
+}
+
+// MARK: impl Readiness
+
+impl Readiness {
+    /// Creates new instance that checks the database and external services of
+    /// all enabled anime sources including every configured scraper instance.
+    pub fn new(config: &settings::Settings, pool: ConnectionPool) -> Self {
+        let mut endpoints = vec![];
+        for source in config.enabled_sources() {
+            let services = source.services(config.services());
+            for service in &[services.indexer(), services.import(), services.scraper()] {
+                for url in service.endpoints() {
+                    if !endpoints.contains(&url) {
+                        endpoints.push(url);
+                    }
+                }
+            }
+        }
+
+        Readiness {
+            pool,
+            endpoints,
+            check_timeout: config.health().check_timeout(),
+        }
+    }
+
+    /// Checks that database and external services are reachable.
+    ///
+    /// Returns description of every failed check.
+    pub async fn check(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        let pool = self.pool.clone();
+        match task::spawn_blocking(move || pool.get().map(|_| ())).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => problems.push(format!("database is unavailable: {}", e)),
+            Err(e) => problems.push(format!("database is unavailable: {}", e)),
+        }
+
+        for url in &self.endpoints {
+            if let Err(e) = self.reach(url).await {
+                problems.push(format!("{} is unreachable: {}", url, e));
+            }
+        }
+
+        problems
+    }
+
+    /// Tries to open TCP connection to the service at `url`.
+    async fn reach(&self, url: &str) -> Result<(), String> {
+        let uri: Uri = url.parse().map_err(|e| format!("invalid url: {}", e))?;
+        let host = uri.host().ok_or_else(|| "no host in url".to_string())?;
+        let port = uri.port_u16().unwrap_or_else(|| match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        });
+
+        match time::timeout(self.check_timeout, TcpStream::connect((host, port))).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err("connection timed out".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::Liveness;
+    use crate::db::entity::Source;
+
+    #[test]
+    fn test_stalled_plans() {
+        let liveness = liveness(Duration::from_secs(0));
+
+        liveness.started(Source::Anidb);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(liveness.stalled(), vec![Source::Anidb]);
+
+        liveness.finished(Source::Anidb);
+        assert!(liveness.stalled().is_empty());
+    }
+
+    #[test]
+    fn test_dead_plans() {
+        let liveness = liveness(Duration::from_secs(60));
+
+        liveness.guard(Source::Anidb).stopped();
+        assert!(liveness.dead().is_empty());
+
+        let guard = liveness.guard(Source::Anidb);
+        let crashed = std::thread::spawn(move || {
+            let _guard = guard;
+            panic!("plan crashed");
+        });
+
+        assert!(crashed.join().is_err());
+        assert_eq!(liveness.dead(), vec![Source::Anidb]);
+    }
+
+    fn liveness(stall_timeout: Duration) -> Liveness {
+        Liveness {
+            busy_since: Arc::new(Mutex::new(HashMap::new())),
+            dead: Arc::new(Mutex::new(HashSet::new())),
+            stall_timeout,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Error as HyperError, Method, Request, Response, Server, StatusCode,
+};
+use tracing::{error, warn};
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use crate::{
+    db::ConnectionPool,
+    health::{Liveness, Readiness},
+    metrics,
+};
+
+/// State shared between HTTP request handlers.
+#[derive(Debug, Clone)]
+struct Context {
+    /// Database connection pool to report metrics for.
+    pool: ConnectionPool,
+
+    /// Progress tracker of scraping plans.
+    liveness: Liveness,
+
+    /// Checks availability of the app dependencies.
+    readiness: Readiness,
+}
+
+/// Runs HTTP server with service endpoints on `addr`.
+///
+/// Exposes Prometheus metrics on `/metrics`, liveness check on `/healthz` and
+/// readiness check on `/readyz`.
+pub async fn serve(
+    addr: SocketAddr,
+    pool: ConnectionPool,
+    liveness: Liveness,
+    readiness: Readiness,
+) -> Result<(), HyperError> {
+    let context = Context {
+        pool,
+        liveness,
+        readiness,
+    };
+
+    let make_service = make_service_fn(move |_| {
+        let context = context.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let context = context.clone();
+                async move { Ok::<_, Infallible>(route(req, context).await) }
+            }))
+        }
+    });
+
+    Server::bind(&addr).serve(make_service).await
+}
+
+/// Dispatches request to it's handler.
+async fn route(req: Request<Body>, context: Context) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => export_metrics(&context.pool),
+        (&Method::GET, "/healthz") => check_liveness(&context.liveness),
+        (&Method::GET, "/readyz") => check_readiness(&context.readiness).await,
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Returns all app metrics in Prometheus text format.
+fn export_metrics(pool: &ConnectionPool) -> Response<Body> {
+    let (content_type, body) = match metrics::encode(pool) {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            error!("failed to encode metrics: {}", e);
+            return status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Reports whether scraping plans are making progress.
+fn check_liveness(liveness: &Liveness) -> Response<Body> {
+    let mut problems = vec![];
+    let stalled = liveness.stalled();
+    if !stalled.is_empty() {
+        problems.push(format!("stalled plans: {:?}", stalled));
+    }
+
+    let dead = liveness.dead();
+    if !dead.is_empty() {
+        problems.push(format!("stopped plans: {:?}", dead));
+    }
+
+    if problems.is_empty() {
+        return text(StatusCode::OK, "ok".to_string());
+    }
+
+    warn!("liveness check failed: {:?}", &problems);
+    text(StatusCode::SERVICE_UNAVAILABLE, problems.join("\n"))
+}
+
+/// Reports whether the app dependencies are available.
+async fn check_readiness(readiness: &Readiness) -> Response<Body> {
+    let problems = readiness.check().await;
+    if problems.is_empty() {
+        return text(StatusCode::OK, "ok".to_string());
+    }
+
+    warn!("readiness check failed: {:?}", &problems);
+    text(StatusCode::SERVICE_UNAVAILABLE, problems.join("\n"))
+}
+
+/// Returns plain text response with provided status code.
+fn text(code: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = code;
+    response
+}
+
+/// Returns empty response with provided status code.
+fn status(code: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = code;
+    response
+}
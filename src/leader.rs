@@ -0,0 +1,183 @@
+use futures::future;
+use tokio::{sync::watch, task, time};
+use tracing::{error, info, warn};
+
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{db::lock::AdvisoryLock, metrics, settings, shutdown::Shutdown};
+
+/// Competes with other scheduler replicas for leadership.
+///
+/// The leader is a replica that holds PostgreSQL advisory lock. It renews it's lease by
+/// checking that the lock is still held and steps down as soon as the check fails, since
+/// the server may have released the lock already. If the check doesn't respond, the
+/// leader steps down once the lease is over. Standbys try to take the lock periodically,
+/// so they take over once the lock is released by the leader or by the server after
+/// leader's connection is gone.
+#[derive(Debug)]
+pub struct Election {
+    /// Lock held by the leader.
+    lock: Arc<Mutex<AdvisoryLock>>,
+
+    /// How often to renew the lease or try to take the lock.
+    renew_interval: Duration,
+
+    /// How long leadership is kept without successful renewal.
+    lease: Duration,
+
+    /// Notifies plan runners about leadership changes.
+    tx: watch::Sender<bool>,
+}
+
+/// Tells whether this replica is the leader.
+#[derive(Debug, Clone)]
+pub struct Leadership(watch::Receiver<bool>);
+
+// MARK: impl Election
+
+impl Election {
+    /// Creates new election and an observer of it's outcome.
+    ///
+    /// The replica is a standby until it wins the election. The lock is held by it's own
+    /// connection to the database, separate from the connection pool.
+    pub fn new(db: &settings::Db, settings: &settings::Leader) -> (Self, Leadership) {
+        let (tx, rx) = watch::channel(false);
+        let lock = AdvisoryLock::new(db.url(), settings.lock_key());
+        let election = Election {
+            lock: Arc::new(Mutex::new(lock)),
+            renew_interval: settings.renew_interval(),
+            lease: settings.lease(),
+            tx,
+        };
+
+        (election, Leadership(rx))
+    }
+
+    /// Takes part in the election until `stop` is requested and releases leadership after.
+    pub async fn run(self, mut stop: Shutdown) {
+        let mut leader = false;
+        let mut renewed_at = Instant::now();
+        while !stop.is_requested() {
+            let held = match time::timeout(self.renew_interval, self.try_lead()).await {
+                Ok(held) => Some(held),
+                Err(_) => {
+                    warn!("leadership check timed out");
+                    None
+                }
+            };
+
+            let elected = match held {
+                Some(true) => {
+                    renewed_at = Instant::now();
+                    true
+                }
+                Some(false) => false,
+                None => leader && renewed_at.elapsed() < self.lease,
+            };
+
+            if elected != leader {
+                leader = elected;
+                self.set_leader(leader);
+                if leader {
+                    info!("became leader");
+                } else {
+                    warn!("lost leadership");
+                    self.release().await;
+                }
+            }
+
+            tokio::select! {
+                _ = time::delay_for(self.renew_interval) => {}
+                _ = stop.wait() => {}
+            }
+        }
+
+        if leader {
+            info!("stepping down");
+            self.set_leader(false);
+            self.release().await;
+        }
+    }
+
+    /// Takes the lock or renews the lease if it's already taken.
+    ///
+    /// Returns `true` if the lock is held. Failed check is treated as lost lock, so the
+    /// leader steps down and releases it or closes it's connection if that fails too.
+    async fn try_lead(&self) -> bool {
+        // server drops the lock after about 1.5 lease without a response from us, so we
+        // step down before a standby is able to take over if the check hangs
+        let (lock, keepalive) = (self.lock.clone(), self.lease / 2);
+        match task::spawn_blocking(move || lock_state(&lock).try_acquire(keepalive)).await {
+            Ok(Ok(held)) => held,
+            Ok(Err(e)) => {
+                warn!("failed to check leadership: {}", e);
+                false
+            }
+            Err(e) => {
+                error!("failed to check leadership: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Releases the lock so standbys could take over immediately.
+    async fn release(&self) {
+        let lock = self.lock.clone();
+        match task::spawn_blocking(move || lock_state(&lock).release()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!("failed to release leadership lock: {}", e),
+            Err(e) => error!("failed to release leadership lock: {}", e),
+        }
+    }
+
+    fn set_leader(&self, leader: bool) {
+        metrics::set_leader(leader);
+        // fails only if there's no plan runners
+        let _ = self.tx.broadcast(leader);
+    }
+}
+
+// MARK: impl Leadership
+
+impl Leadership {
+    /// Returns leadership that is always held, used when there's a single replica.
+    pub fn always() -> Self {
+        let (_, rx) = watch::channel(true);
+        metrics::set_leader(true);
+        Leadership(rx)
+    }
+
+    /// Returns `true` if this replica is the leader.
+    pub fn is_leader(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until this replica becomes the leader.
+    pub async fn acquired(&mut self) {
+        self.wait_for(true).await
+    }
+
+    /// Waits until this replica is not the leader anymore.
+    pub async fn lost(&mut self) {
+        self.wait_for(false).await
+    }
+
+    /// Waits until leadership has the expected value.
+    ///
+    /// If the election is stopped before that the method will never return.
+    async fn wait_for(&mut self, leader: bool) {
+        while self.is_leader() != leader {
+            if self.0.recv().await.is_none() {
+                future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+fn lock_state(lock: &Mutex<AdvisoryLock>) -> MutexGuard<'_, AdvisoryLock> {
+    // lock state is consistent even if a query has panicked
+    lock.lock().unwrap_or_else(|e| e.into_inner())
+}
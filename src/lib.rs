@@ -2,7 +2,16 @@ extern crate openssl;  // fix linkage on musl
 #[macro_use]
 extern crate diesel;
 
+pub mod cli;
+pub mod control;
 pub mod db;
+pub mod health;
+pub mod http;
+pub mod leader;
+pub mod metrics;
 pub mod plan;
 pub mod proto;
+pub mod rpc;
+pub mod runner;
 pub mod settings;
+pub mod shutdown;
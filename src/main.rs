@@ -1,20 +1,28 @@
 extern crate openssl;  // fix linkage on musl
 
-use std::time::Duration;
-
-use tokio::time;
+use futures::future;
+use structopt::StructOpt;
 use tracing::{error, info, info_span};
 use tracing_futures::Instrument as _;
 use tracing_subscriber::{filter::LevelFilter, FmtSubscriber};
 
 use satelit_scheduler::{
-    db::{self, entity::Source, import::FailedImports, index::IndexFiles},
-    plan::{IndexURLBuilder, ScrapePlan},
+    cli::{self, Command, Opts},
+    control::Control,
+    db::{self, repo::Repositories, ConnectionPool},
+    health::{Liveness, Readiness},
+    http,
+    leader::{Election, Leadership},
+    plan::ClientRegistry,
+    rpc,
+    runner::PlanRunner,
     settings::Settings,
+    shutdown,
 };
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = Opts::from_args();
     let subscriber = FmtSubscriber::builder()
         .with_max_level(LevelFilter::DEBUG)
         .finish();
@@ -22,40 +30,122 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("loading configuration");
     let config = Settings::new()?;
-    let url_builder =
-        IndexURLBuilder::new(config.services().indexer().url().to_string(), Source::Anidb);
-
-    info!("connecting to database");
-    let pool = db::new_connection_pool(config.db())?;
-    let index_files = IndexFiles::new(pool.clone());
-    let failed_imports = FailedImports::new(pool);
-
-    loop {
-        let services = config.services().clone();
-        let url_builder = url_builder.clone();
-        let index_files = index_files.clone();
-        let failed_imports = failed_imports.clone();
-
-        let scrape_runner = async move {
-            info!("running scraping plan");
-            let plan = ScrapePlan::new(services, url_builder, index_files, failed_imports);
-            let res = plan.run().await;
-
-            match res {
-                Ok(more) => {
-                    info!("scrape succeeded, has more data to srape: {}", more);
-                    if !more {
-                        info!("nothing to scrape anymore, waiting for 24h");
-                        time::delay_for(Duration::from_secs(24 * 60 * 60)).await;
-                    }
-                }
-                Err(e) => {
-                    error!("scraping plan failed: {:?}", e);
-                    time::delay_for(Duration::from_secs(60)).await;
-                }
+
+    let command = opts.command.unwrap_or(Command::Run);
+    let code = if opts.no_db {
+        info!("running without database, plan state is kept in memory");
+        cli::without_db(&config, command).await
+    } else {
+        info!("connecting to database");
+        let pool = db::new_connection_pool(config.db())?;
+        let repos = Repositories::postgres(pool.clone());
+
+        match command {
+            Command::Run => {
+                let registry = ClientRegistry::new(&config)?;
+                run(config, pool, registry).await;
+                cli::EXIT_OK
+            }
+            Command::Once { source } => cli::once(&config, source, repos).await,
+            Command::UpdateIndex { source } => cli::update_index(&config, source, repos).await,
+            Command::Import { index_id } => cli::import(&config, index_id, pool).await,
+            Command::Scrape { source } => cli::scrape(&config, source, repos).await,
+            Command::Status { source } => cli::status(&config, source, pool).await,
+        }
+    };
+
+    if code != cli::EXIT_OK {
+        std::process::exit(code);
+    }
+
+    Ok(())
+}
+
+/// Runs scraping plans of all enabled sources until shutdown signal is received.
+async fn run(config: Settings, pool: ConnectionPool, registry: ClientRegistry) {
+    let control = Control::new(config.enabled_sources().map(|s| s.name()));
+    let (leadership, election) = if config.leader().election() {
+        let (election, leadership) = Election::new(config.db(), config.leader());
+        (leadership, Some(election))
+    } else {
+        (Leadership::always(), None)
+    };
+
+    let rpc_config = config.rpc().clone();
+    let (rpc_pool, rpc_control, rpc_leadership) =
+        (pool.clone(), control.clone(), leadership.clone());
+    info!("starting rpc server on {}", rpc_config.address());
+    tokio::spawn(async move {
+        if let Err(e) = rpc::serve(rpc_config, rpc_pool, rpc_control, rpc_leadership).await {
+            error!("rpc server failed: {}", e);
+        }
+    });
+
+    let liveness = Liveness::new(config.health());
+    let readiness = Readiness::new(&config, pool.clone());
+    let http_addr = config.http().address();
+    let (http_pool, http_liveness) = (pool.clone(), liveness.clone());
+    info!("starting http server on {}", http_addr);
+    tokio::spawn(async move {
+        if let Err(e) = http::serve(http_addr, http_pool, http_liveness, readiness).await {
+            error!("http server failed: {}", e);
+        }
+    });
+
+    let (trigger, shutdown) = shutdown::channel();
+    tokio::spawn(async move {
+        if let Err(e) = shutdown::signal_received().await {
+            error!("failed to listen for shutdown signals: {}", e);
+            return;
+        }
+
+        info!("shutdown signal received, stopping");
+        trigger.shutdown();
+    });
+
+    let (stop_election, election_stopped) = shutdown::channel();
+    let election = election.map(|election| {
+        info!("starting leader election");
+        let span = info_span!("election");
+        tokio::spawn(election.run(election_stopped).instrument(span))
+    });
+
+    let mut runners = vec![];
+    for source in config.enabled_sources() {
+        let clients = match registry.clients(source.name()) {
+            Ok(clients) => clients,
+            Err(e) => {
+                error!("failed to start scraping plan: {}", e);
+                continue;
             }
         };
 
-        scrape_runner.instrument(info_span!("plan")).await;
+        let runner = PlanRunner::new(
+            &config,
+            source,
+            pool.clone(),
+            clients,
+            liveness.clone(),
+            control.clone(),
+        );
+
+        info!("starting scraping plan for {:?}", source.name());
+        let span = info_span!("plan", source = ?source.name());
+        let run = runner.run(shutdown.clone(), leadership.clone());
+        runners.push(tokio::spawn(run.instrument(span)));
+    }
+
+    for res in future::join_all(runners).await {
+        if let Err(e) = res {
+            error!("scraping plan crashed: {}", e);
+        }
+    }
+
+    // leadership is kept until plans are stopped so standbys don't start them too early
+    stop_election.shutdown();
+    if let Some(election) = election {
+        if let Err(e) = election.await {
+            error!("leader election crashed: {}", e);
+        }
     }
 }
@@ -0,0 +1,214 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramTimer, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    db::{
+        entity::{Phase, Source},
+        ConnectionPool,
+    },
+    plan::PlanError,
+};
+
+lazy_static! {
+    /// Duration of scraping plan phases.
+    static ref PHASE_DURATION: HistogramVec = register_histogram_vec!(
+        "scheduler_phase_duration_seconds",
+        "Duration of scraping plan phases",
+        &["source", "phase"],
+        exponential_buckets(0.5, 4.0, 10).unwrap()
+    )
+    .unwrap();
+
+    /// Number of failed plan runs by error kind.
+    static ref PLAN_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "scheduler_plan_errors_total",
+        "Number of failed scraping plan runs by error kind",
+        &["source", "kind"]
+    )
+    .unwrap();
+
+    /// Number of scraper instances that failed to process a scrape intent.
+    static ref SCRAPER_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "scheduler_scraper_failures_total",
+        "Number of scraper instances that failed to process a scrape intent",
+        &["source"]
+    )
+    .unwrap();
+
+    /// Number of latest index checks by whether index has changed.
+    static ref INDEX_CHECKS: IntCounterVec = register_int_counter_vec!(
+        "scheduler_index_checks_total",
+        "Number of latest index requests by whether index has changed",
+        &["source", "result"]
+    )
+    .unwrap();
+
+    /// Number of index files that are not imported yet.
+    static ref PENDING_INDEX_FILES: IntGaugeVec = register_int_gauge_vec!(
+        "scheduler_pending_index_files",
+        "Number of index files waiting to be imported",
+        &["source"]
+    )
+    .unwrap();
+
+    /// Number of anime titles that failed to import and not reimported yet.
+    static ref FAILED_IMPORT_TITLES: IntGaugeVec = register_int_gauge_vec!(
+        "scheduler_failed_import_titles",
+        "Number of failed to import titles waiting to be reimported",
+        &["source"]
+    )
+    .unwrap();
+
+    /// Number of anime titles that failed to import too many times.
+    static ref QUARANTINED_TITLES: IntGaugeVec = register_int_gauge_vec!(
+        "scheduler_quarantined_titles",
+        "Number of failed to import titles that are not reimported anymore",
+        &["source"]
+    )
+    .unwrap();
+
+    /// Whether scraper reported that there's more data to scrape.
+    static ref MAY_CONTINUE: IntGaugeVec = register_int_gauge_vec!(
+        "scheduler_scrape_may_continue",
+        "Whether last scrape reported that there's more data to scrape",
+        &["source"]
+    )
+    .unwrap();
+
+    /// Time of the last successful plan run.
+    static ref LAST_SUCCESS: IntGaugeVec = register_int_gauge_vec!(
+        "scheduler_last_success_timestamp_seconds",
+        "Unix timestamp of the last successful scraping plan run",
+        &["source"]
+    )
+    .unwrap();
+
+    /// Whether this replica is the leader.
+    static ref LEADER: IntGauge = register_int_gauge!(
+        "scheduler_leader",
+        "Whether this scheduler replica is the leader running scraping plans"
+    )
+    .unwrap();
+
+    /// Number of open database connections.
+    static ref POOL_CONNECTIONS: IntGauge = register_int_gauge!(
+        "scheduler_db_pool_connections",
+        "Number of open database connections"
+    )
+    .unwrap();
+
+    /// Number of idle database connections.
+    static ref POOL_IDLE_CONNECTIONS: IntGauge = register_int_gauge!(
+        "scheduler_db_pool_idle_connections",
+        "Number of idle database connections"
+    )
+    .unwrap();
+
+    /// Maximum number of database connections.
+    static ref POOL_MAX_CONNECTIONS: IntGauge = register_int_gauge!(
+        "scheduler_db_pool_max_connections",
+        "Maximum number of database connections"
+    )
+    .unwrap();
+}
+
+/// Starts measuring duration of a plan phase.
+///
+/// Duration is recorded when returned timer is dropped.
+pub fn phase_timer(source: Source, phase: Phase) -> HistogramTimer {
+    PHASE_DURATION
+        .with_label_values(&[source_label(source), phase_label(phase)])
+        .start_timer()
+}
+
+/// Records failed plan run.
+pub fn plan_failed(source: Source, error: &PlanError) {
+    PLAN_ERRORS
+        .with_label_values(&[source_label(source), error.kind()])
+        .inc();
+}
+
+/// Records successful plan run.
+pub fn plan_succeeded(source: Source, may_continue: bool, at: DateTime<Utc>) {
+    MAY_CONTINUE
+        .with_label_values(&[source_label(source)])
+        .set(may_continue as i64);
+    set_last_success(source, at);
+}
+
+/// Records scraper instance that failed to process a scrape intent.
+pub fn scraper_failed(source: Source) {
+    SCRAPER_FAILURES
+        .with_label_values(&[source_label(source)])
+        .inc();
+}
+
+/// Records latest index request that either returned new index or was not modified.
+pub fn index_checked(source: Source, modified: bool) {
+    let result = if modified { "modified" } else { "not_modified" };
+    INDEX_CHECKS
+        .with_label_values(&[source_label(source), result])
+        .inc();
+}
+
+/// Updates time of the last successful plan run.
+pub fn set_last_success(source: Source, at: DateTime<Utc>) {
+    LAST_SUCCESS
+        .with_label_values(&[source_label(source)])
+        .set(at.timestamp());
+}
+
+/// Updates number of pending index files, failed to import and quarantined titles.
+pub fn set_backlog(source: Source, pending_indexes: i64, failed_titles: i64, quarantined: i64) {
+    let label = source_label(source);
+    PENDING_INDEX_FILES
+        .with_label_values(&[label])
+        .set(pending_indexes);
+    FAILED_IMPORT_TITLES
+        .with_label_values(&[label])
+        .set(failed_titles);
+    QUARANTINED_TITLES
+        .with_label_values(&[label])
+        .set(quarantined);
+}
+
+/// Records whether this replica is the leader.
+pub fn set_leader(leader: bool) {
+    LEADER.set(leader as i64);
+}
+
+/// Returns all metrics in Prometheus text format along with it's content type.
+pub fn encode(pool: &ConnectionPool) -> Result<(String, Vec<u8>), prometheus::Error> {
+    let state = pool.state();
+    POOL_CONNECTIONS.set(state.connections as i64);
+    POOL_IDLE_CONNECTIONS.set(state.idle_connections as i64);
+    POOL_MAX_CONNECTIONS.set(pool.max_size() as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buf = vec![];
+    encoder.encode(&prometheus::gather(), &mut buf)?;
+
+    Ok((encoder.format_type().to_string(), buf))
+}
+
+fn source_label(source: Source) -> &'static str {
+    match source {
+        Source::Anidb => "anidb",
+        Source::Mal => "mal",
+        Source::Ann => "ann",
+    }
+}
+
+fn phase_label(phase: Phase) -> &'static str {
+    match phase {
+        Phase::UpdateIndex => "update_index",
+        Phase::ImportIndex => "import_index",
+        Phase::ScrapeData => "scrape_data",
+    }
+}
@@ -1,60 +1,71 @@
+pub mod client;
+pub mod error;
+pub mod fanout;
 pub mod import;
 pub mod index;
+pub mod registry;
+pub mod retry;
 pub mod scrape;
 
-use reqwest::{Client, Error as HttpError};
-use tokio::task::JoinError;
-use tonic::{transport::Error as TransportError, Status};
-use tracing::{info, instrument};
+pub use client::{Clients, Importer, Indexer, Scraper};
+pub use error::{Cause, ImportError, IndexError, PlanError, ScrapeError};
+pub use registry::ClientRegistry;
+
+use tokio::task;
+use tracing::{error, info, instrument, warn};
 use tracing_futures::Instrument;
 
+use std::sync::Arc;
+
 use crate::{
     db::{
-        entity::{IndexFile, Source},
-        import::FailedImports,
-        index::IndexFiles,
+        entity::{IndexFile, IndexState, Outcome, Phase, PlanRun, Source},
+        repo::{
+            ImportRepository, IndexRepository, IntentRepository, Repositories, RunRepository,
+            ScrapeIntentRepository,
+        },
         QueryError,
     },
-    proto::{
-        import::import_service_client::ImportServiceClient,
-        scraping::scraper_service_client::ScraperServiceClient,
-    },
-    settings::Service,
+    metrics,
+    proto::uuid::Uuid,
+    settings::Recovery,
 };
 
-/// Errors that may happen during scraping plan execution.
+/// Represents end-to-end scraping run.
 #[derive(Debug)]
-pub enum PlanError {
-    /// Failed to access database.
-    StorageError(QueryError),
+pub struct ScrapePlan {
+    /// Anime source to scrape.
+    source: Source,
 
-    /// Failed to connect to external gRPC services.
-    TransportError(TransportError),
+    /// Anime indexing service client.
+    indexer: Arc<dyn Indexer>,
 
-    /// External gRPC service returned an error.
-    ServiceError(Status),
+    /// Anime importing service client.
+    importer: Arc<dyn Importer>,
 
-    /// External HTTP service returned an error.
-    HttpError(HttpError),
+    /// Anime scraping service client.
+    scraper: Arc<dyn Scraper>,
 
-    /// Something unexpected happened.
-    UnexpectedError(Box<dyn std::error::Error + Send>),
-}
+    /// Storage of processed or pending index files.
+    index_files: Arc<dyn IndexRepository>,
 
-/// Represents end-to-end scraping run.
-#[derive(Debug)]
-pub struct ScrapePlan {
-    /// Configuration for external services.
-    service_config: Service,
+    /// Storage of failed to parse anime entries.
+    failed_imports: Arc<dyn ImportRepository>,
 
-    /// URL builder to access anime indexing service.
-    url_builder: IndexURLBuilder,
+    /// Storage of intents sent to importer service.
+    import_intents: Arc<dyn IntentRepository>,
 
-    /// Database access layer to access processed or pending index files.
-    index_files: IndexFiles,
+    /// Storage of intents sent to scraping service.
+    scrape_intents: Arc<dyn ScrapeIntentRepository>,
 
-    /// Database access layer to access failed to parse anime entries.
-    failed_imports: FailedImports,
+    /// Storage to record plan execution history.
+    plan_runs: Arc<dyn RunRepository>,
+
+    /// How many times to try importing an index file before skipping it.
+    max_import_attempts: u32,
+
+    /// How many times an anime title may fail to import before it's quarantined.
+    max_title_attempts: u32,
 }
 
 /// Builds URLs to access anime indexing service.
@@ -72,16 +83,24 @@ pub struct IndexURLBuilder {
 impl ScrapePlan {
     /// Creates new scraping plan instance.
     pub fn new(
-        service_config: Service,
-        url_builder: IndexURLBuilder,
-        index_files: IndexFiles,
-        failed_imports: FailedImports,
+        source: Source,
+        clients: Clients,
+        repos: Repositories,
+        max_import_attempts: u32,
+        max_title_attempts: u32,
     ) -> Self {
         ScrapePlan {
-            service_config,
-            url_builder,
-            index_files,
-            failed_imports,
+            source,
+            indexer: clients.indexer,
+            importer: clients.importer,
+            scraper: clients.scraper,
+            index_files: repos.index_files,
+            failed_imports: repos.failed_imports,
+            import_intents: repos.import_intents,
+            scrape_intents: repos.scrape_intents,
+            plan_runs: repos.plan_runs,
+            max_import_attempts,
+            max_title_attempts,
         }
     }
 
@@ -91,17 +110,143 @@ impl ScrapePlan {
     ///
     /// Returns `Ok(true)` if there's more data to scrape. In that case it's fine to run
     /// the plan again. Or error in case of any errors.
+    ///
+    /// Every run is recorded to the database along with it's outcome.
     #[instrument(skip(self))]
     pub async fn run(&self) -> Result<bool, PlanError> {
+        let source = self.source;
+        let run = self.record(move |runs| runs.start(source)).await?;
+        info!("started plan run: {}", &run.id);
+
+        let res = self.run_phases(&run).in_current_span().await;
+        let (outcome, err) = match res {
+            Ok(_) => (Outcome::Succeeded, None),
+            Err(ref e) => (Outcome::Failed, Some(e.report().to_string())),
+        };
+
+        let finished = self
+            .record(move |runs| runs.finish(&run, outcome, err.as_deref()))
+            .await;
+        if let Err(e) = finished {
+            error!("failed to record plan run outcome: {}", e);
+        }
+
+        res
+    }
+
+    /// Reconciles import and scrape intents that were sent before the app has stopped
+    /// and never completed.
+    ///
+    /// Depending on the `policy` intents are either sent again with the same ID or
+    /// marked as abandoned. Failure to reconcile an intent is logged and the rest are
+    /// reconciled anyway. Returns number of outstanding intents.
+    #[instrument(skip(self))]
+    pub async fn recover(&self, policy: Recovery) -> Result<usize, PlanError> {
+        let (import_intents, scrape_intents) =
+            (self.import_intents.clone(), self.scrape_intents.clone());
+        let source = self.source;
+        let (imports, scrapes) = task::spawn_blocking(move || -> Result<_, QueryError> {
+            Ok((
+                import_intents.outstanding(source)?,
+                scrape_intents.outstanding(source)?,
+            ))
+        })
+        .await??;
+
+        let count = imports.len() + scrapes.len();
+        for intent in imports {
+            let (index_id, intent_id) = (intent.index_id.clone(), intent.id.clone());
+            let res = match policy {
+                Recovery::Reissue => {
+                    info!("reissuing import intent {}", &intent_id);
+                    let res = self.import().resume(intent).in_current_span().await;
+                    self.import_finished(index_id, intent_id, res).await
+                }
+                Recovery::Abandon => {
+                    warn!("abandoning import intent {}", &intent_id);
+                    self.abandon_import(index_id, intent_id).await
+                }
+            };
+
+            if let Err(e) = res {
+                error!("failed to recover: {}", e.report());
+            }
+        }
+
+        for intent in scrapes {
+            let intent_id = intent.id.clone();
+            let res = match policy {
+                Recovery::Reissue => {
+                    info!("reissuing scrape intent {}", &intent_id);
+                    self.scrape().resume(intent).in_current_span().await
+                }
+                Recovery::Abandon => {
+                    warn!("abandoning scrape intent {}", &intent_id);
+                    self.abandon_scrape(intent_id.clone()).await
+                }
+            };
+
+            if let Err(cause) = res {
+                let err = ScrapeError {
+                    source,
+                    intent_id,
+                    cause,
+                };
+                error!("failed to recover: {}", PlanError::from(err).report());
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Runs all plan phases one by one and tracks their progress in `run`.
+    async fn run_phases(&self, run: &PlanRun) -> Result<bool, PlanError> {
+        let source = self.source;
+
         info!("trying to update index");
+        let timer = metrics::phase_timer(source, Phase::UpdateIndex);
         let index = self.update_index().in_current_span().await?;
-        if index.pending {
+        timer.observe_duration();
+
+        if index.needs_import(self.max_import_attempts) {
+            let intent_id = Uuid::new();
+            let (r, id) = (run.clone(), intent_id.clone());
+            self.record(move |runs| runs.import_started(&r, &id))
+                .await?;
+
             info!("importing new index: {}", &index.id);
-            self.import_index(index).in_current_span().await?;
+            let timer = metrics::phase_timer(source, Phase::ImportIndex);
+            self.import_index(index, intent_id)
+                .in_current_span()
+                .await?;
+            timer.observe_duration();
+        } else if index.state == IndexState::Unchanged {
+            info!("index {} is the same as imported one, skipping", &index.id);
+        } else if index.state != IndexState::Imported {
+            warn!(
+                "skipping import of index {} in {:?} state after {} attempts",
+                &index.id, index.state, index.attempts
+            );
         }
 
+        let intent_id = Uuid::new();
+        let (r, id) = (run.clone(), intent_id.clone());
+        self.record(move |runs| runs.scrape_started(&r, &id))
+            .await?;
+
         info!("starting scraping data");
-        self.scrape_data().in_current_span().await
+        let _timer = metrics::phase_timer(source, Phase::ScrapeData);
+        self.scrape_data(intent_id).in_current_span().await
+    }
+
+    /// Updates plan execution history.
+    async fn record<F>(&self, f: F) -> Result<PlanRun, PlanError>
+    where
+        F: FnOnce(&dyn RunRepository) -> Result<PlanRun, QueryError> + Send + 'static,
+    {
+        let plan_runs = self.plan_runs.clone();
+        let run = task::spawn_blocking(move || f(&*plan_runs)).await??;
+        Ok(run)
     }
 
     /// Updates anime index by synchronizing with remote indexing service.
@@ -109,23 +254,113 @@ impl ScrapePlan {
     /// # Return
     ///
     /// Returns latest anime index that should be used for scraping or error in case if update failed.
-    /// If index's `pending` field is `true`, it should be imported by importer service first.
-    async fn update_index(&self) -> Result<IndexFile, PlanError> {
-        let client = Client::new();
-        let check = index::UpdateIndex::new(&client, &self.index_files, &self.url_builder);
-        check.latest_index().in_current_span().await
+    /// If index's `needs_import` returns `true`, it should be imported by importer service first.
+    pub async fn update_index(&self) -> Result<IndexFile, PlanError> {
+        let update = index::UpdateIndex::new(&*self.indexer, self.index_files.clone(), self.source);
+        update
+            .latest_index()
+            .in_current_span()
+            .await
+            .map_err(|cause| {
+                IndexError {
+                    source: self.source,
+                    url: self.indexer.url(self.source),
+                    cause,
+                }
+                .into()
+            })
     }
 
     /// Asks importer service to import anime index.
     ///
     /// # Return
     ///
-    /// Returns an error in case if import failed.
-    async fn import_index(&self, index: IndexFile) -> Result<(), PlanError> {
-        let url = self.service_config.import().url().to_string();
-        let client = ImportServiceClient::connect(url).await?;
-        let mut import = import::ImportIndex::new(client, &self.index_files, &self.failed_imports);
-        import.start_import(index).in_current_span().await
+    /// Returns an error in case if import failed. Failed import attempt is recorded to the index file.
+    pub async fn import_index(&self, index: IndexFile, intent_id: Uuid) -> Result<(), PlanError> {
+        let index_id = index.id.clone();
+        let res = self
+            .import()
+            .start_import(index, intent_id.clone())
+            .in_current_span()
+            .await;
+        self.import_finished(index_id, intent_id, res).await
+    }
+
+    /// Records failed import attempt to the index file if the import has failed.
+    async fn import_finished(
+        &self,
+        index_id: Uuid,
+        intent_id: Uuid,
+        mut res: Result<(), Cause>,
+    ) -> Result<(), PlanError> {
+        let source = self.source;
+        if let Err(ref e) = res {
+            warn!("failed to import index {}: {}", &index_id, e);
+            let (index_files, id) = (self.index_files.clone(), index_id.clone());
+            match task::spawn_blocking(move || index_files.mark_failed(&id)).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => res = Err(e.into()),
+                Err(e) => res = Err(e.into()),
+            }
+        }
+
+        res.map_err(|cause| {
+            ImportError {
+                source,
+                index_id,
+                intent_id,
+                cause,
+            }
+            .into()
+        })
+    }
+
+    /// Marks import intent as abandoned and records failed import attempt to the index file.
+    async fn abandon_import(&self, index_id: Uuid, intent_id: Uuid) -> Result<(), PlanError> {
+        let (import_intents, index_files) = (self.import_intents.clone(), self.index_files.clone());
+        let (intent, index) = (intent_id.clone(), index_id.clone());
+        let res = task::spawn_blocking(move || -> Result<_, QueryError> {
+            import_intents.mark_abandoned(&intent)?;
+            index_files.mark_failed(&index)
+        })
+        .await;
+
+        let cause: Cause = match res {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(e)) => e.into(),
+            Err(e) => e.into(),
+        };
+
+        Err(ImportError {
+            source: self.source,
+            index_id,
+            intent_id,
+            cause,
+        }
+        .into())
+    }
+
+    /// Marks scrape intent as abandoned.
+    async fn abandon_scrape(&self, intent_id: Uuid) -> Result<(), Cause> {
+        let scrape_intents = self.scrape_intents.clone();
+        task::spawn_blocking(move || scrape_intents.mark_abandoned(&intent_id)).await??;
+        Ok(())
+    }
+
+    /// Returns importer of the index file.
+    fn import(&self) -> import::ImportIndex<'_> {
+        import::ImportIndex::new(
+            &*self.importer,
+            self.index_files.clone(),
+            self.failed_imports.clone(),
+            self.import_intents.clone(),
+            self.max_title_attempts,
+        )
+    }
+
+    /// Returns scraper of the anime data.
+    fn scrape(&self) -> scrape::ScrapeData<'_> {
+        scrape::ScrapeData::new(&*self.scraper, self.scrape_intents.clone(), self.source)
     }
 
     /// Asks scraping service to start anime scraping.
@@ -135,11 +370,23 @@ impl ScrapePlan {
     /// If scraping succeeded and there's more data to scrape, `Ok(true)` is returned,
     /// `Ok(false)` is scraping succeeded and there's no more data to scrape. `Err` is
     /// returned in case if scraping failed.
-    async fn scrape_data(&self) -> Result<bool, PlanError> {
-        let url = self.service_config.scraper().url().to_string();
-        let client = ScraperServiceClient::connect(url).await?;
-        let mut scrape = scrape::ScrapeData::new(client, self.url_builder.source());
-        scrape.start_scraping().in_current_span().await?;
+    pub async fn scrape_data(&self, intent_id: Uuid) -> Result<bool, PlanError> {
+        self.start_scraping(intent_id.clone())
+            .await
+            .map_err(|cause| {
+                ScrapeError {
+                    source: self.source,
+                    intent_id,
+                    cause,
+                }
+                .into()
+            })
+    }
+
+    /// Asks scraping service to scrape the data and waits until it's done.
+    async fn start_scraping(&self, intent_id: Uuid) -> Result<bool, Cause> {
+        let mut scrape = self.scrape();
+        scrape.start_scraping(intent_id).in_current_span().await?;
         Ok(scrape.should_scrape())
     }
 }
@@ -166,38 +413,158 @@ impl IndexURLBuilder {
     fn source_path(&self) -> &'static str {
         match self.source {
             Source::Anidb => "anidb",
+            Source::Mal => "mal",
+            Source::Ann => "ann",
         }
     }
 }
 
-// MARK: impl PlanError
+#[cfg(test)]
+mod tests {
+    use tonic::Status;
+
+    use super::*;
+    use crate::{
+        db::entity::{IndexValidators, IntentState},
+        proto::{
+            import::{ImportIntent, ImportIntentResult},
+            scraping::{ScrapeIntent, ScrapeIntentResult},
+        },
+    };
+    use client::NewIndex;
+
+    /// Services where everything but importer works.
+    #[derive(Debug)]
+    struct FakeServices;
 
-impl From<Status> for PlanError {
-    fn from(e: Status) -> Self {
-        PlanError::ServiceError(e)
+    #[tonic::async_trait]
+    impl Indexer for FakeServices {
+        fn url(&self, _source: Source) -> String {
+            "fake://latest".to_string()
+        }
+
+        async fn latest(
+            &self,
+            source: Source,
+            _validators: Option<IndexValidators>,
+        ) -> Result<Option<NewIndex>, Cause> {
+            Ok(Some(NewIndex {
+                id: "index-1".to_string(),
+                file_path: "fake://index-1".to_string(),
+                source,
+                hash: None,
+                etag: None,
+                last_modified: None,
+            }))
+        }
     }
-}
 
-impl From<QueryError> for PlanError {
-    fn from(e: QueryError) -> Self {
-        PlanError::StorageError(e)
+    #[tonic::async_trait]
+    impl Importer for FakeServices {
+        async fn import(&self, _intent: ImportIntent) -> Result<ImportIntentResult, Cause> {
+            Err(Status::unavailable("importer is down").into())
+        }
     }
-}
 
-impl From<HttpError> for PlanError {
-    fn from(e: HttpError) -> Self {
-        PlanError::HttpError(e)
+    #[tonic::async_trait]
+    impl Scraper for FakeServices {
+        async fn instances(&self) -> Result<Vec<String>, Cause> {
+            Ok(vec!["fake://scraper".to_string()])
+        }
+
+        async fn scrape(
+            &self,
+            _url: &str,
+            intent: ScrapeIntent,
+        ) -> Result<ScrapeIntentResult, Cause> {
+            Ok(ScrapeIntentResult {
+                id: intent.id,
+                may_continue: false,
+            })
+        }
     }
-}
 
-impl From<JoinError> for PlanError {
-    fn from(e: JoinError) -> Self {
-        PlanError::UnexpectedError(Box::new(e))
+    /// Returns plan for AniDB that uses `FakeServices` and in-memory repositories.
+    fn plan() -> (ScrapePlan, Repositories) {
+        let services = Arc::new(FakeServices);
+        let clients = Clients {
+            indexer: services.clone(),
+            importer: services.clone(),
+            scraper: services,
+        };
+
+        let repos = Repositories::memory();
+        let plan = ScrapePlan::new(Source::Anidb, clients, repos.clone(), 3, 3);
+        (plan, repos)
     }
-}
 
-impl From<TransportError> for PlanError {
-    fn from(e: TransportError) -> Self {
-        PlanError::TransportError(e)
+    /// Returns `plan()` with an index being imported, and import and scrape intents
+    /// that were sent but never completed.
+    fn plan_with_outstanding_intents() -> (ScrapePlan, Repositories, IndexFile, Uuid, Uuid) {
+        let (plan, repos) = plan();
+        let index = repos
+            .index_files
+            .queue("fake://index-1", Source::Anidb, None)
+            .unwrap();
+        let index = repos.index_files.mark_importing(&index).unwrap();
+        let import_id = Uuid::new();
+        repos
+            .import_intents
+            .create(&import_id, &index, None, &[])
+            .unwrap();
+        let scrape_id = Uuid::new();
+        repos
+            .scrape_intents
+            .create(&scrape_id, Source::Anidb, "fake://scraper")
+            .unwrap();
+
+        (plan, repos, index, import_id, scrape_id)
+    }
+
+    #[tokio::test]
+    async fn test_failed_import_stops_plan() {
+        let (plan, repos) = plan();
+        let (index_id, intent_id) = match plan.run().await {
+            Err(PlanError::ImportIndex(e)) => (e.index_id, e.intent_id),
+            res => panic!("unexpected result: {:?}", res),
+        };
+
+        let index = repos.index_files.find(&index_id).unwrap();
+        assert_eq!(index.state, IndexState::Failed);
+        assert_eq!(index.attempts, 1);
+
+        let intent = repos.import_intents.find(&intent_id).unwrap();
+        assert_eq!(intent.state, IntentState::Failed);
+        assert_eq!(intent.index_id, index_id);
+    }
+
+    #[tokio::test]
+    async fn test_outstanding_intents_are_recovered() {
+        let (plan, repos, index, import_id, scrape_id) = plan_with_outstanding_intents();
+        assert_eq!(plan.recover(Recovery::Reissue).await.unwrap(), 2);
+
+        // importer is down, so the reissued import fails
+        let intent = repos.import_intents.find(&import_id).unwrap();
+        assert_eq!(intent.state, IntentState::Failed);
+        let index = repos.index_files.find(&index.id).unwrap();
+        assert_eq!(index.state, IndexState::Failed);
+
+        let intent = repos.scrape_intents.find(&scrape_id).unwrap();
+        assert_eq!(intent.state, IntentState::Applied);
+        assert_eq!(intent.may_continue, Some(false));
+        assert_eq!(plan.recover(Recovery::Reissue).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_outstanding_intents_are_abandoned() {
+        let (plan, repos, index, import_id, scrape_id) = plan_with_outstanding_intents();
+        assert_eq!(plan.recover(Recovery::Abandon).await.unwrap(), 2);
+
+        let intent = repos.import_intents.find(&import_id).unwrap();
+        assert_eq!(intent.state, IntentState::Abandoned);
+        let index = repos.index_files.find(&index.id).unwrap();
+        assert_eq!(index.state, IndexState::Failed);
+        let intent = repos.scrape_intents.find(&scrape_id).unwrap();
+        assert_eq!(intent.state, IntentState::Abandoned);
     }
 }
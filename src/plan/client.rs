@@ -0,0 +1,416 @@
+use reqwest::{
+    header::{HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time;
+use tonic::transport::{Channel, Endpoint};
+use tracing::{info, warn};
+
+use std::{
+    convert::TryInto,
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use super::{fanout::FanOutScraper, Cause, IndexURLBuilder};
+use crate::{
+    db::entity::{IndexValidators, Source},
+    proto::{
+        import::{import_service_client::ImportServiceClient, ImportIntent, ImportIntentResult},
+        scraping::{
+            scraper_service_client::ScraperServiceClient, ScrapeIntent, ScrapeIntentResult,
+        },
+    },
+    settings::{RemoteServiceConfig, Service},
+};
+
+/// Anime indexing service that provides latest index files.
+#[tonic::async_trait]
+pub trait Indexer: Debug + Send + Sync {
+    /// Returns location of the latest index file info of the `source`.
+    fn url(&self, source: Source) -> String;
+
+    /// Returns latest index file info of the `source`.
+    ///
+    /// If `validators` of a previous response are provided and index has not changed
+    /// since then, `None` is returned.
+    async fn latest(
+        &self,
+        source: Source,
+        validators: Option<IndexValidators>,
+    ) -> Result<Option<NewIndex>, Cause>;
+}
+
+/// Anime importing service that imports index files.
+#[tonic::async_trait]
+pub trait Importer: Debug + Send + Sync {
+    /// Imports index file and waits until it's done.
+    async fn import(&self, intent: ImportIntent) -> Result<ImportIntentResult, Cause>;
+}
+
+/// Anime scraping service that scrapes data of imported titles.
+///
+/// The service may have several instances which scrape data independently.
+#[tonic::async_trait]
+pub trait Scraper: Debug + Send + Sync {
+    /// Returns URLs of currently known service instances.
+    async fn instances(&self) -> Result<Vec<String>, Cause>;
+
+    /// Scrapes anime data by the instance at `url` and waits until it's done.
+    async fn scrape(&self, url: &str, intent: ScrapeIntent) -> Result<ScrapeIntentResult, Cause>;
+}
+
+/// Latest index file info returned by indexing service.
+#[derive(Debug, Clone)]
+pub struct NewIndex {
+    /// Index file identifier.
+    pub id: String,
+
+    /// Path to the file in an index storage.
+    ///
+    /// Content hash is requested from it only if it's an absolute http(s) URL.
+    pub file_path: String,
+
+    /// Type of DB index file relates to.
+    pub source: Source,
+
+    /// Hash of the file content if it's known.
+    pub hash: Option<String>,
+
+    /// `ETag` of the response to send with the next request.
+    pub etag: Option<String>,
+
+    /// `Last-Modified` date of the response to send with the next request.
+    pub last_modified: Option<String>,
+}
+
+/// Clients of external services used by scraping plans.
+#[derive(Debug, Clone)]
+pub struct Clients {
+    /// Anime indexing service client.
+    pub indexer: Arc<dyn Indexer>,
+
+    /// Anime importing service client.
+    pub importer: Arc<dyn Importer>,
+
+    /// Anime scraping service client.
+    pub scraper: Arc<dyn Scraper>,
+}
+
+/// Requests latest index files from indexing service over HTTP.
+#[derive(Debug, Clone)]
+pub struct HttpIndexer {
+    /// HTTP client.
+    client: Client,
+
+    /// Base URL of the service.
+    base_url: String,
+}
+
+/// Imports index files by calling importing gRPC service.
+#[derive(Debug, Clone)]
+pub struct GrpcImporter {
+    /// Channel to the importing service.
+    channel: LazyChannel,
+}
+
+/// Scrapes anime data by calling scraping gRPC service.
+#[derive(Debug, Clone)]
+pub struct GrpcScraper {
+    /// Channel to the scraping service.
+    channel: LazyChannel,
+}
+
+/// gRPC channel to a remote service that is opened on first use.
+///
+/// Opened channel is shared by all clones and reconnects by itself if connection
+/// is lost. Failed connection attempts are not remembered so the next call will try
+/// to connect again.
+#[derive(Debug, Clone)]
+pub struct LazyChannel {
+    /// Remote service configuration.
+    config: RemoteServiceConfig,
+
+    /// Opened channel if any.
+    channel: Arc<Mutex<Option<Channel>>>,
+}
+
+/// Represents lates anime index file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NewIndexFile {
+    /// Index file identifier.
+    id: String,
+
+    /// Path to the file in an index storage.
+    file_path: String,
+
+    /// Type of DB index file relates to.
+    source: i32,
+
+    /// Hash of the file content if provided by the indexer.
+    #[serde(default)]
+    hash: Option<String>,
+}
+
+// MARK: impl Clients
+
+impl Clients {
+    /// Returns clients that talk to services from the configuration.
+    ///
+    /// Clients don't share connections with any other clients. Use `ClientRegistry`
+    /// to reuse connections between sources.
+    pub fn new(services: &Service) -> Result<Self, Cause> {
+        Ok(Clients {
+            indexer: Arc::new(HttpIndexer::new(services.indexer())?),
+            importer: Arc::new(GrpcImporter::new(LazyChannel::new(
+                services.import().clone(),
+            ))),
+            scraper: Arc::new(FanOutScraper::new(services.scraper().clone())),
+        })
+    }
+}
+
+// MARK: impl HttpIndexer
+
+impl HttpIndexer {
+    /// Creates new client that respects remote service timeouts.
+    pub fn new(config: &RemoteServiceConfig) -> Result<Self, Cause> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = config.connection_timeout() {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = config.request_timeout() {
+            builder = builder.timeout(timeout);
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| Cause::Config(format!("failed to build http client: {}", e)))?;
+
+        Ok(HttpIndexer {
+            client,
+            base_url: config.url().to_string(),
+        })
+    }
+
+    /// Returns strong `ETag` of the index file to use as it's content hash.
+    ///
+    /// `file_path` must be an absolute http(s) URL, otherwise no request is made.
+    /// Errors are ignored since the hash is only used to skip unnecessary imports.
+    async fn content_tag(&self, file_path: &str) -> Option<String> {
+        let file_url = match index_file_url(file_path) {
+            Some(url) => url,
+            None => {
+                warn!("index file path is not an absolute url: {}", file_path);
+                return None;
+            }
+        };
+
+        let resp = match self.client.head(file_url).send().await {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(resp) => {
+                warn!("failed to get index file etag: {}", resp.status());
+                return None;
+            }
+            Err(e) => {
+                warn!("failed to get index file etag: {}", e);
+                return None;
+            }
+        };
+
+        let tag = header_value(&resp, ETAG)?;
+        if tag.starts_with("W/") {
+            return None;
+        }
+
+        Some(tag)
+    }
+}
+
+#[tonic::async_trait]
+impl Indexer for HttpIndexer {
+    fn url(&self, source: Source) -> String {
+        IndexURLBuilder::new(self.base_url.clone(), source).latest()
+    }
+
+    async fn latest(
+        &self,
+        source: Source,
+        validators: Option<IndexValidators>,
+    ) -> Result<Option<NewIndex>, Cause> {
+        let url = self.url(source);
+        info!("requesting latest index from {}", &url);
+
+        let mut req = self.client.get(&url);
+        if let Some(validators) = &validators {
+            if let Some(etag) = &validators.etag {
+                req = req.header(IF_NONE_MATCH, etag.as_str());
+            }
+
+            if let Some(last_modified) = &validators.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified.as_str());
+            }
+        }
+
+        let resp = req.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED && validators.is_some() {
+            return Ok(None);
+        }
+
+        let resp = resp.error_for_status()?;
+        let etag = header_value(&resp, ETAG);
+        let last_modified = header_value(&resp, LAST_MODIFIED);
+        let new_index = resp.json::<NewIndexFile>().await?;
+        let hash = match new_index.hash {
+            Some(hash) => Some(hash),
+            None => self.content_tag(&new_index.file_path).await,
+        };
+
+        Ok(Some(NewIndex {
+            id: new_index.id,
+            file_path: new_index.file_path,
+            source: new_index.source.try_into()?,
+            hash,
+            etag,
+            last_modified,
+        }))
+    }
+}
+
+// MARK: impl GrpcImporter
+
+impl GrpcImporter {
+    pub fn new(channel: LazyChannel) -> Self {
+        GrpcImporter { channel }
+    }
+}
+
+#[tonic::async_trait]
+impl Importer for GrpcImporter {
+    async fn import(&self, intent: ImportIntent) -> Result<ImportIntentResult, Cause> {
+        let mut client = ImportServiceClient::new(self.channel.get().await?);
+        let timeout = self.channel.config().request_timeout();
+        let resp = deadline(timeout, client.start_import(intent)).await?;
+        Ok(resp.into_inner())
+    }
+}
+
+// MARK: impl GrpcScraper
+
+impl GrpcScraper {
+    pub fn new(channel: LazyChannel) -> Self {
+        GrpcScraper { channel }
+    }
+
+    /// Scrapes anime data and waits until it's done.
+    pub async fn scrape(&self, intent: ScrapeIntent) -> Result<ScrapeIntentResult, Cause> {
+        let mut client = ScraperServiceClient::new(self.channel.get().await?);
+        let timeout = self.channel.config().request_timeout();
+        let resp = deadline(timeout, client.start_scraping(intent)).await?;
+        Ok(resp.into_inner())
+    }
+}
+
+// MARK: impl LazyChannel
+
+impl LazyChannel {
+    /// Creates new channel that will connect to the service on first use.
+    pub fn new(config: RemoteServiceConfig) -> Self {
+        LazyChannel {
+            config,
+            channel: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns configuration of the remote service.
+    pub fn config(&self) -> &RemoteServiceConfig {
+        &self.config
+    }
+
+    /// Returns opened channel, connecting to the service if it's not connected yet.
+    pub async fn get(&self) -> Result<Channel, Cause> {
+        let opened = self.lock().clone();
+        if let Some(channel) = opened {
+            return Ok(channel);
+        }
+
+        info!("connecting to {}", self.config.url());
+        let channel = connect(&self.config).await?;
+
+        // concurrent callers may have connected already, keep the first channel
+        let mut opened = self.lock();
+        Ok(opened.get_or_insert(channel).clone())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Channel>> {
+        // the value is only replaced while locked so it's safe to ignore poisoning
+        self.channel.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Returns value of the response header if it's present and valid.
+fn header_value(resp: &Response, name: HeaderName) -> Option<String> {
+    let value = resp.headers().get(name)?.to_str().ok()?;
+    Some(value.to_string())
+}
+
+/// Returns URL of the index file if it's path is an absolute http(s) URL.
+fn index_file_url(file_path: &str) -> Option<Url> {
+    let url = Url::parse(file_path).ok()?;
+    match url.scheme() {
+        "http" | "https" => Some(url),
+        _ => None,
+    }
+}
+
+/// Opens gRPC channel to a remote service.
+///
+/// Connection attempt will fail with `Cause::Timeout` if it takes longer than
+/// service's connection timeout. Request timeout is applied to every RPC call made
+/// through the channel. If TCP keepalive is configured, idle connections are probed
+/// with it since the transport doesn't support HTTP/2 pings.
+async fn connect(config: &RemoteServiceConfig) -> Result<Channel, Cause> {
+    let mut endpoint = Endpoint::new(config.url().to_string())
+        .map_err(|e| Cause::Config(format!("invalid service url {}: {}", config.url(), e)))?;
+    if let Some(timeout) = config.request_timeout() {
+        endpoint = endpoint.timeout(timeout);
+    }
+
+    if let Some(interval) = config.tcp_keepalive() {
+        endpoint = endpoint.tcp_keepalive(Some(interval));
+    }
+
+    deadline(config.connection_timeout(), endpoint.connect()).await
+}
+
+/// Awaits for the future to complete but not longer than `timeout` if it's provided.
+async fn deadline<F, T, E>(timeout: Option<Duration>, fut: F) -> Result<T, Cause>
+where
+    F: Future<Output = Result<T, E>>,
+    Cause: From<E>,
+{
+    match timeout {
+        Some(timeout) => Ok(time::timeout(timeout, fut).await??),
+        None => Ok(fut.await?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_file_url() {
+        let url = index_file_url("http://storage/anidb/index-1.json.gz").unwrap();
+        assert_eq!(url.as_str(), "http://storage/anidb/index-1.json.gz");
+        assert!(index_file_url("https://storage/anidb/index-1.json.gz").is_some());
+
+        assert!(index_file_url("anidb/index-1.json.gz").is_none());
+        assert!(index_file_url("/anidb/index-1.json.gz").is_none());
+        assert!(index_file_url("s3://bucket/anidb/index-1.json.gz").is_none());
+    }
+}
@@ -0,0 +1,394 @@
+use reqwest::Error as HttpError;
+use tokio::{task::JoinError, time::Elapsed};
+use tonic::{transport::Error as TransportError, Status};
+
+use std::{error::Error, fmt, io};
+
+use crate::{
+    db::{entity::Source, QueryError},
+    proto::uuid::Uuid,
+};
+
+/// Errors that may happen during scraping plan execution.
+#[derive(Debug)]
+pub enum PlanError {
+    /// Failed to fetch latest anime index.
+    UpdateIndex(IndexError),
+
+    /// Failed to import anime index.
+    ImportIndex(ImportError),
+
+    /// Failed to scrape anime data.
+    ScrapeData(ScrapeError),
+
+    /// Failed outside of plan phases, e.g. while recording plan execution history.
+    Other(Cause),
+}
+
+/// Failed to fetch latest anime index from indexing service.
+#[derive(Debug)]
+pub struct IndexError {
+    /// Anime source of the index.
+    pub source: Source,
+
+    /// URL of the latest index file info.
+    pub url: String,
+
+    /// What went wrong.
+    pub cause: Cause,
+}
+
+/// Failed to import anime index by importing service.
+#[derive(Debug)]
+pub struct ImportError {
+    /// Anime source of the index.
+    pub source: Source,
+
+    /// Index file being imported.
+    pub index_id: Uuid,
+
+    /// Import intent sent to importing service.
+    pub intent_id: Uuid,
+
+    /// What went wrong.
+    pub cause: Cause,
+}
+
+/// Failed to scrape anime data by scraping service.
+#[derive(Debug)]
+pub struct ScrapeError {
+    /// Anime source to scrape data from.
+    pub source: Source,
+
+    /// Scrape intent sent to scraping service.
+    pub intent_id: Uuid,
+
+    /// What went wrong.
+    pub cause: Cause,
+}
+
+/// Underlying reason of a plan failure.
+///
+/// Message of the cause already includes the error of an external service or library,
+/// so it's the last error in the chain and doesn't have a source.
+#[derive(Debug)]
+pub enum Cause {
+    /// Failed to access database.
+    Storage(QueryError),
+
+    /// Failed to connect to external gRPC services.
+    Transport(TransportError),
+
+    /// External gRPC service returned an error.
+    Service(Status),
+
+    /// External HTTP service returned an error.
+    Http(HttpError),
+
+    /// External service didn't respond within configured timeout.
+    Timeout,
+
+    /// External service returned unknown anime source.
+    InvalidSource(i32),
+
+    /// External service returned response that doesn't make sense.
+    InvalidResponse(String),
+
+    /// Failed to find instances of a remote service.
+    Discovery(io::Error),
+
+    /// Remote service configuration is invalid.
+    Config(String),
+
+    /// Blocking task has panicked or has been cancelled.
+    Task(JoinError),
+}
+
+/// Formats the error together with all of it's sources, e.g. for logs.
+pub struct Report<'a>(&'a dyn Error);
+
+// MARK: impl PlanError
+
+impl PlanError {
+    /// Returns underlying reason of the failure.
+    pub fn cause(&self) -> &Cause {
+        use PlanError::*;
+
+        match self {
+            UpdateIndex(e) => &e.cause,
+            ImportIndex(e) => &e.cause,
+            ScrapeData(e) => &e.cause,
+            Other(cause) => cause,
+        }
+    }
+
+    /// Returns short name of the error kind suitable for metric labels.
+    pub fn kind(&self) -> &'static str {
+        self.cause().kind()
+    }
+
+    /// Returns full error message with the underlying reason of the failure.
+    pub fn report(&self) -> Report<'_> {
+        Report(self)
+    }
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PlanError::*;
+
+        match self {
+            UpdateIndex(e) => e.fmt(f),
+            ImportIndex(e) => e.fmt(f),
+            ScrapeData(e) => e.fmt(f),
+            Other(cause) => cause.fmt(f),
+        }
+    }
+}
+
+impl Error for PlanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use PlanError::*;
+
+        match self {
+            UpdateIndex(e) => e.source(),
+            ImportIndex(e) => e.source(),
+            ScrapeData(e) => e.source(),
+            Other(cause) => cause.source(),
+        }
+    }
+}
+
+impl From<IndexError> for PlanError {
+    fn from(e: IndexError) -> Self {
+        PlanError::UpdateIndex(e)
+    }
+}
+
+impl From<ImportError> for PlanError {
+    fn from(e: ImportError) -> Self {
+        PlanError::ImportIndex(e)
+    }
+}
+
+impl From<ScrapeError> for PlanError {
+    fn from(e: ScrapeError) -> Self {
+        PlanError::ScrapeData(e)
+    }
+}
+
+impl From<Cause> for PlanError {
+    fn from(cause: Cause) -> Self {
+        PlanError::Other(cause)
+    }
+}
+
+impl From<QueryError> for PlanError {
+    fn from(e: QueryError) -> Self {
+        PlanError::Other(e.into())
+    }
+}
+
+impl From<JoinError> for PlanError {
+    fn from(e: JoinError) -> Self {
+        PlanError::Other(e.into())
+    }
+}
+
+// MARK: impl IndexError
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to update {:?} index from {}",
+            self.source, &self.url
+        )
+    }
+}
+
+impl Error for IndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+// MARK: impl ImportError
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to import {:?} index {} with intent {}",
+            self.source, &self.index_id, &self.intent_id
+        )
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+// MARK: impl ScrapeError
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to scrape {:?} data with intent {}",
+            self.source, &self.intent_id
+        )
+    }
+}
+
+impl Error for ScrapeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+// MARK: impl Cause
+
+impl Cause {
+    /// Returns short name of the cause suitable for metric labels.
+    pub fn kind(&self) -> &'static str {
+        use Cause::*;
+
+        match self {
+            Storage(_) => "storage",
+            Transport(_) => "transport",
+            Service(_) => "service",
+            Http(_) => "http",
+            Timeout => "timeout",
+            InvalidSource(_) => "invalid_source",
+            InvalidResponse(_) => "invalid_response",
+            Discovery(_) => "discovery",
+            Config(_) => "config",
+            Task(_) => "unexpected",
+        }
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Cause::*;
+
+        match self {
+            Storage(e) => write!(f, "database error: {}", e),
+            Transport(e) => write!(f, "failed to connect to service: {}", e),
+            Service(status) => write!(
+                f,
+                "service responded with {:?}: {}",
+                status.code(),
+                status.message()
+            ),
+            Http(e) => write!(f, "http request failed: {}", e),
+            Timeout => write!(f, "service didn't respond in time"),
+            InvalidSource(value) => write!(f, "unknown anime source: {}", value),
+            InvalidResponse(msg) => write!(f, "unexpected response: {}", msg),
+            Discovery(e) => write!(f, "failed to discover service instances: {}", e),
+            Config(msg) => write!(f, "invalid configuration: {}", msg),
+            Task(e) => write!(f, "blocking task failed: {}", e),
+        }
+    }
+}
+
+impl Error for Cause {}
+
+impl From<Status> for Cause {
+    fn from(e: Status) -> Self {
+        Cause::Service(e)
+    }
+}
+
+impl From<QueryError> for Cause {
+    fn from(e: QueryError) -> Self {
+        Cause::Storage(e)
+    }
+}
+
+impl From<HttpError> for Cause {
+    fn from(e: HttpError) -> Self {
+        if e.is_timeout() {
+            return Cause::Timeout;
+        }
+
+        Cause::Http(e)
+    }
+}
+
+impl From<Elapsed> for Cause {
+    fn from(_: Elapsed) -> Self {
+        Cause::Timeout
+    }
+}
+
+impl From<JoinError> for Cause {
+    fn from(e: JoinError) -> Self {
+        Cause::Task(e)
+    }
+}
+
+impl From<TransportError> for Cause {
+    fn from(e: TransportError) -> Self {
+        Cause::Transport(e)
+    }
+}
+
+// MARK: impl Report
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+
+        let mut source = self.0.source();
+        while let Some(e) = source {
+            write!(f, ": {}", e)?;
+            source = e.source();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Status;
+
+    use std::error::Error;
+
+    use super::{Cause, ImportError, PlanError};
+    use crate::{db::entity::Source, proto::uuid::Uuid};
+
+    #[test]
+    fn test_error_context() {
+        let (index_id, intent_id) = (Uuid::new(), Uuid::new());
+        let err = PlanError::from(ImportError {
+            source: Source::Mal,
+            index_id: index_id.clone(),
+            intent_id: intent_id.clone(),
+            cause: Status::unavailable("importer is down").into(),
+        });
+
+        let msg = err.to_string();
+        assert!(msg.contains("Mal"));
+        assert!(msg.contains(&index_id.to_string()));
+        assert!(msg.contains(&intent_id.to_string()));
+        assert!(!msg.contains("importer is down"));
+        assert_eq!(err.kind(), "service");
+
+        let cause = err.source().expect("cause is missing");
+        assert!(cause.to_string().contains("importer is down"));
+        assert!(cause.source().is_none());
+
+        let report = err.report().to_string();
+        assert_eq!(report, format!("{}: {}", msg, cause));
+        assert_eq!(report.matches("importer is down").count(), 1);
+
+        let err = PlanError::from(Cause::Timeout);
+        assert!(err.source().is_none());
+        assert_eq!(err.report().to_string(), "service didn't respond in time");
+    }
+}
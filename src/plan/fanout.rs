@@ -0,0 +1,133 @@
+use hyper::Uri;
+use tokio::net;
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Mutex, MutexGuard},
+};
+
+use super::{
+    client::{GrpcScraper, LazyChannel, Scraper},
+    Cause,
+};
+use crate::{
+    proto::scraping::{ScrapeIntent, ScrapeIntentResult},
+    settings::{Discovery, RemoteServiceConfig},
+};
+
+/// Talks to all instances of scraping service.
+///
+/// Instances are either listed in the configuration or discovered by resolving service
+/// host on every call. Every instance is meant to receive it's own scrape intent.
+#[derive(Debug)]
+pub struct FanOutScraper {
+    /// Scraping service configuration.
+    config: RemoteServiceConfig,
+
+    /// Clients of known service instances by their URL.
+    scrapers: Mutex<HashMap<String, GrpcScraper>>,
+}
+
+// MARK: impl FanOutScraper
+
+impl FanOutScraper {
+    pub fn new(config: RemoteServiceConfig) -> Self {
+        FanOutScraper {
+            config,
+            scrapers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns client of the service instance at `url`.
+    fn scraper(&self, url: &str) -> GrpcScraper {
+        self.lock()
+            .entry(url.to_string())
+            .or_insert_with(|| GrpcScraper::new(LazyChannel::new(self.config.with_url(url))))
+            .clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, GrpcScraper>> {
+        // the map is only modified while locked so it's safe to ignore poisoning
+        self.scrapers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[tonic::async_trait]
+impl Scraper for FanOutScraper {
+    /// Returns URLs of currently known service instances.
+    ///
+    /// Clients of instances that are gone are dropped and their channels are closed.
+    async fn instances(&self) -> Result<Vec<String>, Cause> {
+        let urls = match self.config.discovery() {
+            Discovery::Static => self.config.endpoints(),
+            Discovery::Dns => resolve(self.config.url()).await?,
+        };
+
+        self.lock().retain(|url, _| urls.contains(url));
+        Ok(urls)
+    }
+
+    async fn scrape(&self, url: &str, intent: ScrapeIntent) -> Result<ScrapeIntentResult, Cause> {
+        self.scraper(url).scrape(intent).await
+    }
+}
+
+/// Returns URLs of every address host of the `url` resolves to.
+///
+/// Only plain `http` URLs can be resolved: instances are reached by their IP addresses,
+/// so there's no host name left to verify TLS certificates against.
+async fn resolve(url: &str) -> Result<Vec<String>, Cause> {
+    let uri = url
+        .parse::<Uri>()
+        .map_err(|e| Cause::Config(format!("invalid service url {}: {}", url, e)))?;
+    let (scheme, host) = match (uri.scheme_str(), uri.host()) {
+        (Some(scheme), Some(host)) => (scheme, host),
+        _ => return Err(Cause::Config(format!("service url {} has no host", url))),
+    };
+
+    if scheme != "http" {
+        let msg = format!(
+            "dns discovery doesn't support {} service url {}",
+            scheme, url
+        );
+        return Err(Cause::Config(msg));
+    }
+
+    let port = uri.port_u16().unwrap_or(80);
+    let addrs = net::lookup_host((host, port))
+        .await
+        .map_err(Cause::Discovery)?;
+
+    let mut urls: Vec<String> = addrs.map(|a| format!("{}://{}", scheme, a)).collect();
+    urls.sort();
+    urls.dedup();
+
+    if urls.is_empty() {
+        let msg = format!("{} has no addresses", host);
+        return Err(Cause::Discovery(io::Error::new(
+            io::ErrorKind::NotFound,
+            msg,
+        )));
+    }
+
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_rejects_tls() {
+        let err = resolve("https://localhost:9050").await.unwrap_err();
+        match err {
+            Cause::Config(_) => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let urls = resolve("http://localhost:9050").await.unwrap();
+        assert!(urls.iter().all(|url| url.starts_with("http://")));
+        assert!(urls.iter().all(|url| url.ends_with(":9050")));
+    }
+}
@@ -1,32 +1,39 @@
 use tokio::task;
-use tonic::transport::Channel;
-use tracing::{info, Span};
+use tracing::{info, warn, Span};
 use tracing_futures::Instrument;
 
-use super::PlanError;
+use std::sync::Arc;
+
+use super::{client::Importer, Cause};
 use crate::{
     db::{
-        entity::{self, FailedImport, IndexFile},
-        import::FailedImports,
-        index::IndexFiles,
+        entity::{self, IndexFile},
+        repo::{ImportRepository, IndexRepository, IntentRepository},
+        QueryError,
     },
     proto::{
         data,
-        import::{import_service_client::ImportServiceClient, ImportIntent, ImportIntentResult},
+        import::{ImportIntent, ImportIntentResult},
         uuid::Uuid,
     },
 };
 
 /// Ask import service to start importing new database index file.
 pub struct ImportIndex<'a> {
-    /// RPC client for importing service.
-    client: ImportServiceClient<Channel>,
+    /// Importing service client.
+    importer: &'a dyn Importer,
+
+    /// Storage of all index files.
+    index_files: Arc<dyn IndexRepository>,
+
+    /// Storage of failed to import anime entries.
+    failed_imports: Arc<dyn ImportRepository>,
 
-    /// Database access layer for all index files.
-    index_files: &'a IndexFiles,
+    /// Storage of intents sent to importer service.
+    import_intents: Arc<dyn IntentRepository>,
 
-    /// Database access layer for failed to import anime entries.
-    failed_imports: &'a FailedImports,
+    /// How many times a title may fail to import before it's quarantined.
+    max_title_attempts: u32,
 }
 
 // MARK: impl ImportIndex
@@ -34,99 +41,195 @@ pub struct ImportIndex<'a> {
 impl<'a> ImportIndex<'a> {
     /// Creates new service instance.
     pub fn new(
-        client: ImportServiceClient<Channel>,
-        index_files: &'a IndexFiles,
-        failed_imports: &'a FailedImports,
+        importer: &'a dyn Importer,
+        index_files: Arc<dyn IndexRepository>,
+        failed_imports: Arc<dyn ImportRepository>,
+        import_intents: Arc<dyn IntentRepository>,
+        max_title_attempts: u32,
     ) -> Self {
         ImportIndex {
-            client,
+            importer,
             index_files,
             failed_imports,
+            import_intents,
+            max_title_attempts,
         }
     }
 
     /// Starts import process.
     ///
     /// The method will wait until the import process finish and then update database
-    /// with import result.
-    pub async fn start_import(&mut self, index_file: IndexFile) -> Result<(), PlanError> {
+    /// with import result. Import intent will be sent with provided `intent_id` and
+    /// is recorded before it's sent. The index file is marked as being imported and
+    /// it's attempts counter is increased.
+    pub async fn start_import(
+        &mut self,
+        index_file: IndexFile,
+        intent_id: Uuid,
+    ) -> Result<(), Cause> {
         let failed_imports = self.failed_imports.clone();
-        let index_files = self.index_files.clone();
         let source = index_file.source;
-        let reimport = task::spawn_blocking(move || failed_imports.with_source(source)).await??;
+        let reimport = task::spawn_blocking(move || failed_imports.eligible(source)).await??;
 
-        let (new_index, old_index) = task::spawn_blocking(move || {
-            let old = index_files.latest_processed(&index_file);
-            (index_file, old)
-        })
-        .await?;
-
-        let mut reimport_ids = Vec::<i32>::new();
-        if let Some(ref reimport) = reimport {
-            info!("will reimport ids: {:?}", &reimport.title_ids);
-            reimport_ids.extend(reimport.title_ids.iter());
+        let reimport_ids: Vec<i32> = reimport.iter().map(|f| f.title_id).collect();
+        if !reimport_ids.is_empty() {
+            info!("will reimport ids: {:?}", &reimport_ids);
         }
 
-        let new_url = &new_index.file_path;
-        let old_url = old_index?.map(|i| i.file_path);
-        let intent = ImportIntent {
-            id: Some(Uuid::new()),
-            source: map_source(source) as i32,
-            new_index_url: new_url.to_owned(),
-            old_index_url: old_url.unwrap_or_else(String::new),
-            reimport_ids,
-        };
+        let (index_files, import_intents) = (self.index_files.clone(), self.import_intents.clone());
+        let (new_index, old_index, recorded) =
+            task::spawn_blocking(move || -> Result<_, QueryError> {
+                let old = index_files.latest_processed(&index_file)?;
+                let new = index_files.mark_importing(&index_file)?;
+                let recorded =
+                    import_intents.create(&intent_id, &new, old.as_ref(), &reimport_ids)?;
+                Ok((new, old, recorded))
+            })
+            .await??;
+
+        let intent = new_intent(&recorded, &new_index, old_index.as_ref());
+        self.send(intent, recorded).in_current_span().await
+    }
+
+    /// Sends again import intent that has never completed and applies it's result.
+    ///
+    /// The intent keeps it's ID, so the importer is able to recognize it.
+    pub async fn resume(&mut self, recorded: entity::ImportIntent) -> Result<(), Cause> {
+        let index_files = self.index_files.clone();
+        let (index_id, old_index_id) = (recorded.index_id.clone(), recorded.old_index_id.clone());
+        let (new_index, old_index) = task::spawn_blocking(move || -> Result<_, QueryError> {
+            let new = index_files.find(&index_id)?;
+            let old = old_index_id.map(|id| index_files.find(&id)).transpose()?;
+            Ok((new, old))
+        })
+        .await??;
 
+        let intent = new_intent(&recorded, &new_index, old_index.as_ref());
+        self.send(intent, recorded).in_current_span().await
+    }
+
+    /// Sends recorded import intent and applies it's result.
+    async fn send(
+        &self,
+        intent: ImportIntent,
+        recorded: entity::ImportIntent,
+    ) -> Result<(), Cause> {
         info!(
             "starting import with intent: {}",
             intent.id.as_ref().unwrap()
         );
-        let res = self.client.start_import(intent).await?.into_inner();
-        self.process_result(res, new_index, reimport)
-            .in_current_span()
-            .await
+        let res = match self.importer.import(intent).await {
+            Ok(res) => res,
+            Err(e) => {
+                self.mark_failed(&recorded, &e).await;
+                return Err(e);
+            }
+        };
+
+        self.process_result(res, recorded).in_current_span().await
     }
 
     /// Updates database with import result.
     ///
-    /// The method will update status of failed to import anime entries and
-    /// will mark just processed index file as imported.
+    /// The method will forget reimported anime entries, record another failed attempt
+    /// of skipped ones and will mark just processed index file as imported. All of it
+    /// is applied atomically and only once per intent.
     async fn process_result(
         &self,
         res: ImportIntentResult,
-        index: IndexFile,
-        reimport: Option<FailedImport>,
-    ) -> Result<(), PlanError> {
-        let index_files = self.index_files.clone();
-        let failed_imports = self.failed_imports.clone();
+        intent: entity::ImportIntent,
+    ) -> Result<(), Cause> {
+        let result_id = match res.id {
+            Some(id) if id != intent.id => {
+                let err = Cause::InvalidResponse(format!(
+                    "received result of import intent {} instead of {}",
+                    id, &intent.id
+                ));
+                self.mark_failed(&intent, &err).await;
+                return Err(err);
+            }
+            Some(id) => id,
+            None => {
+                warn!("import result has no intent id, assuming {}", &intent.id);
+                intent.id.clone()
+            }
+        };
+
+        let (reimported, carried) = intent.split_reimported(&res.skipped_ids);
+        if !reimported.is_empty() {
+            info!("marking reimported items: {:?}", &reimported);
+        }
+
+        if !carried.is_empty() {
+            info!("items failed to reimport again: {:?}", &carried);
+        }
+
+        if !res.skipped_ids.is_empty() {
+            info!("memorizing failed to import items: {:?}", &res.skipped_ids);
+        }
 
+        let import_intents = self.import_intents.clone();
+        let max_attempts = self.max_title_attempts;
         let span = Span::current();
         task::spawn_blocking(move || {
             let _enter = span.enter();
 
-            if let Some(reimport) = reimport {
-                info!("marking reimported items: {:?}", &reimport.title_ids);
-                failed_imports.mark_reimported(reimport)?;
-            }
-
-            if !res.skipped_ids.is_empty() {
-                info!("memorizing failed to import items: {:?}", &res.skipped_ids);
-                failed_imports.create(&index, &res.skipped_ids)?;
+            info!("applying result of import intent: {}", &result_id);
+            let (imported, skipped) = (&res.imported_ids, &res.skipped_ids);
+            let failed = match import_intents.apply(&result_id, imported, skipped, max_attempts)? {
+                Some(failed) => failed,
+                None => {
+                    info!("result of import intent {} is already applied", &result_id);
+                    return Ok(());
+                }
+            };
+
+            let quarantined: Vec<i32> = failed
+                .iter()
+                .filter(|f| f.quarantined && f.attempts == max_attempts as i32)
+                .map(|f| f.title_id)
+                .collect();
+
+            if !quarantined.is_empty() {
+                warn!(
+                    "quarantined items after {} attempts: {:?}",
+                    max_attempts, &quarantined
+                );
             }
 
-            info!("marking index file as imported: {}", &index.id);
-            index_files.mark_processed(index)
+            Ok::<_, QueryError>(())
         })
         .await??;
 
         Ok(())
     }
+
+    /// Records that importer service has failed to process the intent.
+    ///
+    /// Failure to record it is only logged so the original error is reported.
+    async fn mark_failed(&self, intent: &entity::ImportIntent, err: &Cause) {
+        let import_intents = self.import_intents.clone();
+        let (id, err) = (intent.id.clone(), err.to_string());
+        match task::spawn_blocking(move || import_intents.mark_failed(&id, &err)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!("failed to record failed import intent: {}", e),
+            Err(e) => warn!("failed to record failed import intent: {}", e),
+        }
+    }
 }
 
-/// Converts domain anime source entry to protobuf's one.
-fn map_source(s: entity::Source) -> data::Source {
-    match s {
-        entity::Source::Anidb => data::Source::Anidb,
+/// Returns import intent to send to importer service for the recorded one.
+fn new_intent(
+    recorded: &entity::ImportIntent,
+    new_index: &IndexFile,
+    old_index: Option<&IndexFile>,
+) -> ImportIntent {
+    ImportIntent {
+        id: Some(recorded.id.clone()),
+        source: data::Source::from(recorded.source) as i32,
+        new_index_url: new_index.file_path.clone(),
+        old_index_url: old_index.map(|i| i.file_path.clone()).unwrap_or_default(),
+        reimport_ids: recorded.reimport_ids.clone(),
     }
 }
 
@@ -136,6 +239,8 @@ impl From<entity::Source> for data::Source {
     fn from(s: entity::Source) -> Self {
         match s {
             entity::Source::Anidb => data::Source::Anidb,
+            entity::Source::Mal => data::Source::Mal,
+            entity::Source::Ann => data::Source::Ann,
         }
     }
 }
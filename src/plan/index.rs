@@ -1,78 +1,98 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
 use tokio::task;
-use tracing::{debug, info};
-
-use std::convert::{TryFrom, TryInto};
-
-use super::{IndexURLBuilder, PlanError};
-use crate::db::{
-    entity::{IndexFile, Source},
-    index::IndexFiles,
+use tracing::{debug, info, warn};
+
+use std::{convert::TryFrom, sync::Arc};
+
+use super::{client::Indexer, Cause};
+use crate::{
+    db::{
+        entity::{IndexFile, Source},
+        repo::IndexRepository,
+        QueryError,
+    },
+    metrics,
 };
 
 /// Service that fetches latest anime index files.
 pub struct UpdateIndex<'a> {
-    /// HTTP client.
-    client: &'a Client,
-
-    /// DB access layer for anime index files.
-    store: &'a IndexFiles,
-
-    /// URL to get info about latest index files.
-    url_builder: &'a IndexURLBuilder,
-}
-
-/// Represents lates anime index file.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct NewIndexFile {
-    /// Index file identifier.
-    id: String,
+    /// Anime indexing service client.
+    indexer: &'a dyn Indexer,
 
-    /// Path to the file in an index storage.
-    file_path: String,
+    /// Storage of anime index files.
+    store: Arc<dyn IndexRepository>,
 
-    /// Type of DB index file relates to.
-    source: i32,
+    /// Source of the index files.
+    source: Source,
 }
 
 // MARK: impl UpdateIndex
 
 impl<'a> UpdateIndex<'a> {
-    pub fn new(
-        client: &'a Client,
-        store: &'a IndexFiles,
-        url_builder: &'a IndexURLBuilder,
-    ) -> Self {
+    pub fn new(indexer: &'a dyn Indexer, store: Arc<dyn IndexRepository>, source: Source) -> Self {
         UpdateIndex {
-            client,
+            indexer,
             store,
-            url_builder,
+            source,
         }
     }
 
     /// Updates and returns latest anime index file.
     ///
     /// In case if there's new index file available it will be saved to DB with
-    /// `pending == true` status. Otherwise, existing record from the DB will be returned.
-    pub async fn latest_index(&self) -> Result<IndexFile, PlanError> {
-        let url = self.url_builder.latest();
-        info!("requesting latest index from {}", &url);
-
-        let resp = self.client.get(&url).send().await?.error_for_status()?;
-        let new_index = resp.json::<NewIndexFile>().await?;
-        let source = new_index.source.try_into()?;
+    /// `IndexState::Pending` state. Otherwise, existing record from the DB will be returned.
+    /// New index file with the same content as the last imported one is saved with
+    /// `IndexState::Unchanged` state.
+    ///
+    /// Cache validators of the previous response are sent along with the request, so
+    /// indexer may reply that the index is not modified in which case latest known index
+    /// file is returned.
+    pub async fn latest_index(&self) -> Result<IndexFile, Cause> {
+        let source = self.source;
+        let store = self.store.clone();
+        let validators = task::spawn_blocking(move || store.validators(source)).await??;
+
+        let new_index = match self.indexer.latest(source, validators).await? {
+            Some(new_index) => new_index,
+            None => {
+                metrics::index_checked(source, false);
+
+                let store = self.store.clone();
+                match task::spawn_blocking(move || store.latest(source)).await?? {
+                    Some(index) => {
+                        info!("latest index is not modified: {}", &index.id);
+                        return Ok(index);
+                    }
+                    None => {
+                        warn!("latest index is not modified but it's unknown, requesting it again");
+                        self.indexer.latest(source, None).await?.ok_or_else(|| {
+                            Cause::InvalidResponse(
+                                "index is not modified without validators".to_string(),
+                            )
+                        })?
+                    }
+                }
+            }
+        };
+
+        metrics::index_checked(source, true);
         info!("received new index: {}", &new_index.id);
 
         let store = self.store.clone();
         let index = task::spawn_blocking(move || {
             let path = &new_index.file_path;
-            let source = source;
-            store.queue(path, source)
+            let hash = new_index.hash.as_deref();
+            let index = store.queue(path, new_index.source, hash)?;
+            store.save_validators(
+                source,
+                new_index.etag.as_deref(),
+                new_index.last_modified.as_deref(),
+            )?;
+
+            Ok::<_, QueryError>(index)
         })
         .await??;
 
-        debug!(pending = index.pending);
+        debug!(state = ?index.state, attempts = index.attempts);
         Ok(index)
     }
 }
@@ -80,14 +100,14 @@ impl<'a> UpdateIndex<'a> {
 // MARK: impl i32
 
 impl TryFrom<i32> for Source {
-    type Error = PlanError;
+    type Error = Cause;
 
     fn try_from(value: i32) -> Result<Self, Self::Error> {
         match value {
             1 => Ok(Source::Anidb),
-            _ => Err(PlanError::ServiceError(super::Status::internal(
-                "failed to convert index source",
-            ))),
+            2 => Ok(Source::Mal),
+            3 => Ok(Source::Ann),
+            _ => Err(Cause::InvalidSource(value)),
         }
     }
 }
@@ -0,0 +1,77 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::{
+    client::{GrpcImporter, HttpIndexer, LazyChannel},
+    fanout::FanOutScraper,
+    Cause, Clients,
+};
+use crate::{
+    db::entity::Source,
+    settings::{RemoteServiceConfig, Settings},
+};
+
+/// Clients of external services of all anime sources.
+///
+/// Clients are meant to live as long as the app and be reused between plan runs.
+/// Sources that use the same gRPC service configuration share a single client.
+/// Channels are opened on first use.
+#[derive(Debug, Clone)]
+pub struct ClientRegistry {
+    /// Clients of every configured anime source.
+    clients: HashMap<Source, Clients>,
+}
+
+// MARK: impl ClientRegistry
+
+impl ClientRegistry {
+    /// Creates clients for all anime sources from the configuration.
+    pub fn new(config: &Settings) -> Result<Self, Cause> {
+        let mut channels = HashMap::new();
+        let mut scrapers = HashMap::new();
+        let mut clients = HashMap::new();
+        for source in config.sources() {
+            let services = source.services(config.services());
+            let importer = shared_channel(&mut channels, services.import());
+            let scraper = shared_scraper(&mut scrapers, services.scraper());
+            let source_clients = Clients {
+                indexer: Arc::new(HttpIndexer::new(services.indexer())?),
+                importer: Arc::new(GrpcImporter::new(importer)),
+                scraper,
+            };
+
+            clients.insert(source.name(), source_clients);
+        }
+
+        Ok(ClientRegistry { clients })
+    }
+
+    /// Returns clients of the anime `source`.
+    pub fn clients(&self, source: Source) -> Result<Clients, Cause> {
+        self.clients
+            .get(&source)
+            .cloned()
+            .ok_or_else(|| Cause::Config(format!("{:?} source is not configured", source)))
+    }
+}
+
+/// Returns channel to the service, reusing existing one if it has the same configuration.
+fn shared_channel(
+    channels: &mut HashMap<RemoteServiceConfig, LazyChannel>,
+    config: &RemoteServiceConfig,
+) -> LazyChannel {
+    channels
+        .entry(config.clone())
+        .or_insert_with(|| LazyChannel::new(config.clone()))
+        .clone()
+}
+
+/// Returns scraper of the service, reusing existing one if it has the same configuration.
+fn shared_scraper(
+    scrapers: &mut HashMap<RemoteServiceConfig, Arc<FanOutScraper>>,
+    config: &RemoteServiceConfig,
+) -> Arc<FanOutScraper> {
+    scrapers
+        .entry(config.clone())
+        .or_insert_with(|| Arc::new(FanOutScraper::new(config.clone())))
+        .clone()
+}
@@ -0,0 +1,162 @@
+use rand::Rng;
+use tonic::Code;
+
+use std::time::Duration;
+
+use super::{Cause, PlanError};
+use crate::settings::Schedule;
+
+/// Decides when a failed scraping plan should be retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Delay before the first retry.
+    base: Duration,
+
+    /// Maximum delay between retries.
+    max: Duration,
+
+    /// Number of consecutive failures after which the plan is degraded.
+    budget: u32,
+
+    /// Number of consecutive failures so far.
+    failures: u32,
+}
+
+/// What to do with a failed plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    /// Run the plan again after the delay.
+    Retry(Duration),
+
+    /// Stop retrying and wait for the next scheduled run.
+    Degrade,
+}
+
+// MARK: impl RetryPolicy
+
+impl RetryPolicy {
+    /// Creates new policy with `failures` consecutive failures that already happened.
+    pub fn new(schedule: &Schedule, failures: u32) -> Self {
+        RetryPolicy {
+            base: schedule.retry_interval(),
+            max: schedule.max_retry_interval(),
+            budget: schedule.max_failures(),
+            failures,
+        }
+    }
+
+    /// Returns number of consecutive failures.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Resets the policy after successful plan run.
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Registers plan failure and decides what to do next.
+    ///
+    /// Fatal errors degrade the plan immediately. Retryable errors are retried with
+    /// exponential backoff until failure budget is exhausted.
+    pub fn failed(&mut self, err: &PlanError) -> Decision {
+        self.failures = self.failures.saturating_add(1);
+        if !is_retryable(err) || self.failures >= self.budget {
+            return Decision::Degrade;
+        }
+
+        Decision::Retry(self.backoff())
+    }
+
+    /// Returns randomized delay for current number of failures.
+    fn backoff(&self) -> Duration {
+        let exp = self.failures.saturating_sub(1).min(31);
+        let delay = self
+            .base
+            .checked_mul(1 << exp)
+            .map_or(self.max, |d| d.min(self.max));
+
+        // "equal jitter": half of the delay is fixed and another half is random
+        let half = delay.as_millis() as u64 / 2;
+        let jitter = rand::thread_rng().gen_range(0, half + 1);
+        Duration::from_millis(half + jitter)
+    }
+}
+
+/// Returns `true` if the plan that failed with provided error may succeed on retry.
+pub fn is_retryable(err: &PlanError) -> bool {
+    use Cause::*;
+
+    match err.cause() {
+        Storage(_) | Transport(_) | Discovery(_) | Timeout => true,
+        // a request that the service rejects won't be accepted on retry either
+        Service(status) => match status.code() {
+            Code::InvalidArgument
+            | Code::FailedPrecondition
+            | Code::Unimplemented
+            | Code::PermissionDenied
+            | Code::Unauthenticated => false,
+            _ => true,
+        },
+        Http(e) => match e.status() {
+            Some(status) => status.is_server_error(),
+            None => !e.is_decode(),
+        },
+        InvalidSource(_) | InvalidResponse(_) | Config(_) | Task(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Status;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            base: Duration::from_secs(10),
+            max: Duration::from_secs(60),
+            budget: 5,
+            failures: 0,
+        }
+    }
+
+    #[test]
+    fn test_classification() {
+        let unavailable = Cause::Service(Status::unavailable("down")).into();
+        let internal = Cause::Service(Status::internal("crashed")).into();
+        let invalid = Cause::Service(Status::invalid_argument("bad intent")).into();
+
+        assert!(is_retryable(&Cause::Timeout.into()));
+        assert!(is_retryable(&unavailable));
+        assert!(is_retryable(&internal));
+        assert!(!is_retryable(&invalid));
+        assert!(!is_retryable(&Cause::InvalidSource(42).into()));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut policy = policy();
+        let bounds = [(5, 10), (10, 20), (20, 40), (30, 60)];
+        for &(min, max) in &bounds {
+            match policy.failed(&Cause::Timeout.into()) {
+                Decision::Retry(delay) => {
+                    assert!(delay >= Duration::from_secs(min));
+                    assert!(delay <= Duration::from_secs(max));
+                }
+                Decision::Degrade => panic!("degraded too early"),
+            }
+        }
+
+        assert_eq!(policy.failed(&Cause::Timeout.into()), Decision::Degrade);
+        policy.succeeded();
+        assert_eq!(policy.failures(), 0);
+    }
+
+    #[test]
+    fn test_fatal_error_degrades() {
+        let mut policy = policy();
+        let decision = policy.failed(&Cause::InvalidSource(42).into());
+        assert_eq!(decision, Decision::Degrade);
+    }
+}
@@ -1,19 +1,27 @@
-use tonic::transport::Channel;
-use tracing::info;
+use futures::future;
+use tokio::task;
+use tracing::{error, info, warn};
 
-use super::PlanError;
+use std::{io, sync::Arc};
+
+use super::{client::Scraper, Cause};
 use crate::{
-    db::entity::Source,
-    proto::{
-        scraping::{scraper_service_client::ScraperServiceClient, ScrapeIntent},
-        uuid::Uuid,
+    db::{
+        entity::{self, Source},
+        repo::ScrapeIntentRepository,
+        QueryError,
     },
+    metrics,
+    proto::{scraping::ScrapeIntent, uuid::Uuid},
 };
 
 /// Asks scraping RPC service to start anime scraping.
-pub struct ScrapeData {
-    /// RPC service client.
-    client: ScraperServiceClient<Channel>,
+pub struct ScrapeData<'a> {
+    /// Scraping service client.
+    scraper: &'a dyn Scraper,
+
+    /// Storage of intents sent to scraping service.
+    scrape_intents: Arc<dyn ScrapeIntentRepository>,
 
     /// From where to scrape data.
     source: Source,
@@ -24,11 +32,16 @@ pub struct ScrapeData {
 
 // MARK: impl ScrapeData
 
-impl ScrapeData {
+impl<'a> ScrapeData<'a> {
     /// Creates new struct instance.
-    pub fn new(client: ScraperServiceClient<Channel>, source: Source) -> Self {
+    pub fn new(
+        scraper: &'a dyn Scraper,
+        scrape_intents: Arc<dyn ScrapeIntentRepository>,
+        source: Source,
+    ) -> Self {
         ScrapeData {
-            client,
+            scraper,
+            scrape_intents,
             source,
             should_scrape: true,
         }
@@ -51,19 +64,125 @@ impl ScrapeData {
     /// It's still safe to call the method again if `should_scrape()`
     /// returns `false`. The RPC call will be made but scraper service
     /// may return immediatelly.
-    pub async fn start_scraping(&mut self) -> Result<(), PlanError> {
+    ///
+    /// Every scraper instance receives it's own intent: the first one is sent with
+    /// provided `intent_id` and the rest get new IDs. Intents are recorded before
+    /// they're sent. Fails only if all instances have failed, otherwise the work of
+    /// failed ones is left for the next call and `should_scrape()` returns `true`.
+    pub async fn start_scraping(&mut self, intent_id: Uuid) -> Result<(), Cause> {
+        let intents: Vec<_> = self
+            .scraper
+            .instances()
+            .await?
+            .into_iter()
+            .enumerate()
+            .map(|(idx, url)| {
+                let id = if idx == 0 {
+                    intent_id.clone()
+                } else {
+                    Uuid::new()
+                };
+                (url, id)
+            })
+            .collect();
+
+        let (scrape_intents, recorded, source) =
+            (self.scrape_intents.clone(), intents.clone(), self.source);
+        task::spawn_blocking(move || {
+            for (url, id) in &recorded {
+                scrape_intents.create(id, source, url)?;
+            }
+
+            Ok::<_, QueryError>(())
+        })
+        .await??;
+
+        let count = intents.len();
+        let sent = intents.into_iter().map(|(url, id)| self.send(url, id));
+
+        let mut should_scrape = false;
+        let mut failed = 0;
+        let mut last_err = None;
+        for res in future::join_all(sent).await {
+            match res {
+                Ok(more) => should_scrape |= more,
+                Err(e) => {
+                    should_scrape = true;
+                    failed += 1;
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        self.should_scrape = should_scrape;
+        match last_err {
+            Some(e) if failed == count => Err(e),
+            _ => {
+                if failed > 0 {
+                    error!("{} of {} scrapers failed", failed, count);
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends again scrape intent that has never completed and waits until it's done.
+    ///
+    /// The intent keeps it's ID and is sent to the same scraper instance, so the instance
+    /// is able to recognize it. If the instance is gone, it's sent to the first known one.
+    pub async fn resume(&mut self, recorded: entity::ScrapeIntent) -> Result<(), Cause> {
+        let instances = self.scraper.instances().await?;
+        let url = match recorded.endpoint {
+            Some(url) if instances.contains(&url) => url,
+            _ => instances.into_iter().next().ok_or_else(|| {
+                Cause::Discovery(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no scraper instances found",
+                ))
+            })?,
+        };
+
+        self.should_scrape = self.send(url, recorded.id).await?;
+        Ok(())
+    }
+
+    /// Sends recorded scrape intent to the scraper at `url` and records it's result.
+    ///
+    /// Returns `true` if the scraper has more data to scrape.
+    async fn send(&self, url: String, intent_id: Uuid) -> Result<bool, Cause> {
         let intent = ScrapeIntent {
-            id: Some(Uuid::new()),
+            id: Some(intent_id.clone()),
             source: self.source as i32,
         };
 
-        info!(
-            "starting scraping with intent: {}",
-            intent.id.as_ref().unwrap()
-        );
-        let res = self.client.start_scraping(intent).await?;
-        self.should_scrape = res.get_ref().may_continue;
+        info!("sending scrape intent {} to {}", &intent_id, &url);
+        let res = match self.scraper.scrape(&url, intent).await {
+            Ok(res) => res,
+            Err(e) => {
+                error!("scraper {} failed: {}", &url, e);
+                metrics::scraper_failed(self.source);
+                self.mark_failed(&intent_id, &e).await;
+                return Err(e);
+            }
+        };
 
-        Ok(())
+        let (scrape_intents, more) = (self.scrape_intents.clone(), res.may_continue);
+        task::spawn_blocking(move || scrape_intents.complete(&intent_id, more)).await??;
+
+        Ok(more)
+    }
+
+    /// Records that scraping service has failed to process the intent.
+    ///
+    /// Failure to record it is only logged so the original error is reported.
+    async fn mark_failed(&self, intent_id: &Uuid, err: &Cause) {
+        let scrape_intents = self.scrape_intents.clone();
+        let (id, err) = (intent_id.clone(), err.to_string());
+        match task::spawn_blocking(move || scrape_intents.mark_failed(&id, &err)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!("failed to record failed scrape intent: {}", e),
+            Err(e) => warn!("failed to record failed scrape intent: {}", e),
+        }
     }
 }
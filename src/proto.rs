@@ -1,5 +1,6 @@
 #![allow(clippy::all)]
 
+pub mod admin;
 pub mod data;
 pub mod import;
 pub mod scraping;
@@ -8,7 +9,7 @@ pub mod uuid;
 // MARK: uuid::Uuid
 
 pub mod ext {
-    use std::{convert::TryFrom, fmt};
+    use std::{convert::TryFrom, fmt, str::FromStr};
 
     impl super::uuid::Uuid {
         pub fn new() -> Self {
@@ -39,6 +40,15 @@ pub mod ext {
         }
     }
 
+    impl FromStr for super::uuid::Uuid {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let uuid = uuid::Uuid::parse_str(s).map_err(|e| e.to_string())?;
+            Self::try_from(&uuid.as_bytes()[..])
+        }
+    }
+
     impl fmt::Display for super::uuid::Uuid {
         #[allow(clippy::needless_range_loop)]
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
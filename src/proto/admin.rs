@@ -0,0 +1,475 @@
+/// Selects a scraping plan to operate on
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PlanSelector {
+    /// Anime source of the plan, `UNKNOWN` selects all plans
+    #[prost(enumeration = "super::data::Source", tag = "1")]
+    pub source: i32,
+}
+/// Runtime status of scraping plans
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PlanStatusList {
+    /// Status of every selected plan
+    #[prost(message, repeated, tag = "1")]
+    pub plans: ::std::vec::Vec<PlanStatus>,
+}
+/// Runtime status of a scraping plan
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PlanStatus {
+    /// Anime source of the plan
+    #[prost(enumeration = "super::data::Source", tag = "1")]
+    pub source: i32,
+    /// Wherever the plan is paused
+    #[prost(bool, tag = "2")]
+    pub paused: bool,
+    /// Wherever the plan is running right now
+    #[prost(bool, tag = "3")]
+    pub running: bool,
+    /// Wherever the plan has failed too many times in a row
+    #[prost(bool, tag = "4")]
+    pub degraded: bool,
+    /// Number of consecutive failed runs
+    #[prost(sint32, tag = "5")]
+    pub consecutive_failures: i32,
+    /// Error of the last failed run
+    #[prost(string, tag = "6")]
+    pub last_error: std::string::String,
+    /// Timestamp of the next scheduled run (unix), 0 if not scheduled
+    #[prost(sint64, tag = "7")]
+    pub next_run_at: i64,
+    /// Timestamp of the last successful run (unix), 0 if there were none
+    #[prost(sint64, tag = "8")]
+    pub last_success_at: i64,
+}
+/// Asks for scraping plan runs history
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RunListRequest {
+    /// Anime source of the plan, `UNKNOWN` selects all plans
+    #[prost(enumeration = "super::data::Source", tag = "1")]
+    pub source: i32,
+    /// Maximum number of runs to return
+    #[prost(sint32, tag = "2")]
+    pub limit: i32,
+}
+/// Scraping plan runs history, most recent first
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RunList {
+    /// Plan runs
+    #[prost(message, repeated, tag = "1")]
+    pub runs: ::std::vec::Vec<PlanRun>,
+}
+/// A single run of a scraping plan
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PlanRun {
+    /// Run ID
+    #[prost(message, optional, tag = "1")]
+    pub id: ::std::option::Option<super::uuid::Uuid>,
+    /// Anime source of the plan
+    #[prost(enumeration = "super::data::Source", tag = "2")]
+    pub source: i32,
+    /// Last phase reached by the run
+    #[prost(enumeration = "plan_run::Phase", tag = "3")]
+    pub phase: i32,
+    /// Run outcome
+    #[prost(enumeration = "plan_run::Outcome", tag = "4")]
+    pub outcome: i32,
+    /// Error description if the run has failed
+    #[prost(string, tag = "5")]
+    pub error: std::string::String,
+    /// Timestamp of the run start (unix)
+    #[prost(sint64, tag = "6")]
+    pub started_at: i64,
+    /// Timestamp of the run end (unix), 0 if not finished
+    #[prost(sint64, tag = "7")]
+    pub finished_at: i64,
+}
+pub mod plan_run {
+    /// Phase of a scraping plan
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Phase {
+        Unknown = 0,
+        UpdateIndex = 1,
+        ImportIndex = 2,
+        ScrapeData = 3,
+    }
+    /// Outcome of a scraping plan run
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Outcome {
+        Unknown = 0,
+        Running = 1,
+        Succeeded = 2,
+        Failed = 3,
+        Interrupted = 4,
+    }
+}
+#[doc = r" Generated client implementations."]
+pub mod scheduler_admin_client {
+    #![allow(unused_variables, dead_code, missing_docs)]
+    use tonic::codegen::*;
+    #[doc = " A service to control scraping plans of the scheduler"]
+    #[doc = ""]
+    #[doc = " 'Scheduler' implements a server side of the service and it's meant"]
+    #[doc = " to be used by operators. Plans are run by the leader replica only, so"]
+    #[doc = " 'TriggerRun', 'Pause' and 'Resume' fail with 'FAILED_PRECONDITION' on"]
+    #[doc = " standby replicas."]
+    pub struct SchedulerAdminClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl SchedulerAdminClient<tonic::transport::Channel> {
+        #[doc = r" Attempt to create a new client by connecting to a given endpoint."]
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: std::convert::TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> SchedulerAdminClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::ResponseBody: Body + HttpBody + Send + 'static,
+        T::Error: Into<StdError>,
+        <T::ResponseBody as HttpBody>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_interceptor(inner: T, interceptor: impl Into<tonic::Interceptor>) -> Self {
+            let inner = tonic::client::Grpc::with_interceptor(inner, interceptor);
+            Self { inner }
+        }
+        #[doc = " Runs scraping plan as soon as possible"]
+        pub async fn trigger_run(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PlanSelector>,
+        ) -> Result<tonic::Response<()>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/admin.SchedulerAdmin/TriggerRun");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Pauses scraping plan after it's current run"]
+        pub async fn pause(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PlanSelector>,
+        ) -> Result<tonic::Response<()>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/admin.SchedulerAdmin/Pause");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Resumes paused scraping plan"]
+        pub async fn resume(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PlanSelector>,
+        ) -> Result<tonic::Response<()>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/admin.SchedulerAdmin/Resume");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Returns runtime status of scraping plans"]
+        pub async fn get_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PlanSelector>,
+        ) -> Result<tonic::Response<super::PlanStatusList>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/admin.SchedulerAdmin/GetStatus");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " Returns history of scraping plan runs"]
+        pub async fn list_runs(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RunListRequest>,
+        ) -> Result<tonic::Response<super::RunList>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/admin.SchedulerAdmin/ListRuns");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+    }
+    impl<T: Clone> Clone for SchedulerAdminClient<T> {
+        fn clone(&self) -> Self {
+            Self {
+                inner: self.inner.clone(),
+            }
+        }
+    }
+}
+#[doc = r" Generated server implementations."]
+pub mod scheduler_admin_server {
+    #![allow(unused_variables, dead_code, missing_docs)]
+    use tonic::codegen::*;
+    #[doc = "Generated trait containing gRPC methods that should be implemented for use with SchedulerAdminServer."]
+    #[async_trait]
+    pub trait SchedulerAdmin: Send + Sync + 'static {
+        #[doc = " Runs scraping plan as soon as possible"]
+        async fn trigger_run(
+            &self,
+            request: tonic::Request<super::PlanSelector>,
+        ) -> Result<tonic::Response<()>, tonic::Status>;
+        #[doc = " Pauses scraping plan after it's current run"]
+        async fn pause(
+            &self,
+            request: tonic::Request<super::PlanSelector>,
+        ) -> Result<tonic::Response<()>, tonic::Status>;
+        #[doc = " Resumes paused scraping plan"]
+        async fn resume(
+            &self,
+            request: tonic::Request<super::PlanSelector>,
+        ) -> Result<tonic::Response<()>, tonic::Status>;
+        #[doc = " Returns runtime status of scraping plans"]
+        async fn get_status(
+            &self,
+            request: tonic::Request<super::PlanSelector>,
+        ) -> Result<tonic::Response<super::PlanStatusList>, tonic::Status>;
+        #[doc = " Returns history of scraping plan runs"]
+        async fn list_runs(
+            &self,
+            request: tonic::Request<super::RunListRequest>,
+        ) -> Result<tonic::Response<super::RunList>, tonic::Status>;
+    }
+    #[doc = " A service to control scraping plans of the scheduler"]
+    #[doc = ""]
+    #[doc = " 'Scheduler' implements a server side of the service and it's meant"]
+    #[doc = " to be used by operators. Plans are run by the leader replica only, so"]
+    #[doc = " 'TriggerRun', 'Pause' and 'Resume' fail with 'FAILED_PRECONDITION' on"]
+    #[doc = " standby replicas."]
+    #[derive(Debug)]
+    #[doc(hidden)]
+    pub struct SchedulerAdminServer<T: SchedulerAdmin> {
+        inner: _Inner<T>,
+    }
+    struct _Inner<T>(Arc<T>, Option<tonic::Interceptor>);
+    impl<T: SchedulerAdmin> SchedulerAdminServer<T> {
+        pub fn new(inner: T) -> Self {
+            let inner = Arc::new(inner);
+            let inner = _Inner(inner, None);
+            Self { inner }
+        }
+        pub fn with_interceptor(inner: T, interceptor: impl Into<tonic::Interceptor>) -> Self {
+            let inner = Arc::new(inner);
+            let inner = _Inner(inner, Some(interceptor.into()));
+            Self { inner }
+        }
+    }
+    impl<T: SchedulerAdmin> Service<http::Request<HyperBody>> for SchedulerAdminServer<T> {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Never;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<HyperBody>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/admin.SchedulerAdmin/TriggerRun" => {
+                    struct TriggerRunSvc<T: SchedulerAdmin>(pub Arc<T>);
+                    impl<T: SchedulerAdmin> tonic::server::UnaryService<super::PlanSelector> for TriggerRunSvc<T> {
+                        type Response = ();
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PlanSelector>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { inner.trigger_run(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = TriggerRunSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/admin.SchedulerAdmin/Pause" => {
+                    struct PauseSvc<T: SchedulerAdmin>(pub Arc<T>);
+                    impl<T: SchedulerAdmin> tonic::server::UnaryService<super::PlanSelector> for PauseSvc<T> {
+                        type Response = ();
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PlanSelector>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { inner.pause(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = PauseSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/admin.SchedulerAdmin/Resume" => {
+                    struct ResumeSvc<T: SchedulerAdmin>(pub Arc<T>);
+                    impl<T: SchedulerAdmin> tonic::server::UnaryService<super::PlanSelector> for ResumeSvc<T> {
+                        type Response = ();
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PlanSelector>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { inner.resume(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = ResumeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/admin.SchedulerAdmin/GetStatus" => {
+                    struct GetStatusSvc<T: SchedulerAdmin>(pub Arc<T>);
+                    impl<T: SchedulerAdmin> tonic::server::UnaryService<super::PlanSelector> for GetStatusSvc<T> {
+                        type Response = super::PlanStatusList;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PlanSelector>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { inner.get_status(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = GetStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/admin.SchedulerAdmin/ListRuns" => {
+                    struct ListRunsSvc<T: SchedulerAdmin>(pub Arc<T>);
+                    impl<T: SchedulerAdmin> tonic::server::UnaryService<super::RunListRequest> for ListRunsSvc<T> {
+                        type Response = super::RunList;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RunListRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { inner.list_runs(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let interceptor = inner.1.clone();
+                        let inner = inner.0;
+                        let method = ListRunsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = if let Some(interceptor) = interceptor {
+                            tonic::server::Grpc::with_interceptor(codec, interceptor)
+                        } else {
+                            tonic::server::Grpc::new(codec)
+                        };
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => Box::pin(async move {
+                    Ok(http::Response::builder()
+                        .status(200)
+                        .header("grpc-status", "12")
+                        .body(tonic::body::BoxBody::empty())
+                        .unwrap())
+                }),
+            }
+        }
+    }
+    impl<T: SchedulerAdmin> Clone for SchedulerAdminServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self { inner }
+        }
+    }
+    impl<T: SchedulerAdmin> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone(), self.1.clone())
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: SchedulerAdmin> tonic::transport::NamedService for SchedulerAdminServer<T> {
+        const NAME: &'static str = "admin.SchedulerAdmin";
+    }
+}
@@ -25,6 +25,9 @@ pub struct ImportIntentResult {
     /// IDs of anime titles that was not imported
     #[prost(sint32, repeated, tag = "2")]
     pub skipped_ids: ::std::vec::Vec<i32>,
+    /// IDs of anime titles that was imported and should be scheduled for scraping
+    #[prost(sint32, repeated, tag = "3")]
+    pub imported_ids: ::std::vec::Vec<i32>,
 }
 #[doc = r" Generated client implementations."]
 pub mod import_service_client {
@@ -0,0 +1,37 @@
+pub mod admin;
+pub mod tasks;
+
+use tonic::transport::{Error as TransportError, Server};
+
+use crate::{
+    control::Control,
+    db::{runs::PlanRuns, state::PlanStates, tasks::ScrapeTasks, ConnectionPool},
+    leader::Leadership,
+    proto::{
+        admin::scheduler_admin_server::SchedulerAdminServer,
+        scraping::scraper_tasks_service_server::ScraperTasksServiceServer,
+    },
+    settings::Rpc,
+};
+
+/// Serves scheduler's gRPC services on configured address.
+pub async fn serve(
+    config: Rpc,
+    pool: ConnectionPool,
+    control: Control,
+    leadership: Leadership,
+) -> Result<(), TransportError> {
+    let tasks = tasks::ScraperTasks::new(ScrapeTasks::new(pool.clone()), config.task_lease());
+    let admin = admin::Admin::new(
+        control,
+        leadership,
+        PlanStates::new(pool.clone()),
+        PlanRuns::new(pool),
+    );
+
+    Server::builder()
+        .add_service(ScraperTasksServiceServer::new(tasks))
+        .add_service(SchedulerAdminServer::new(admin))
+        .serve(config.address())
+        .await
+}
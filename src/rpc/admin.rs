@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use tokio::task;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+use std::convert::TryFrom;
+
+use crate::{
+    control::Control,
+    db::{
+        entity::{self, Outcome, Phase, Source},
+        runs::PlanRuns,
+        state::PlanStates,
+        QueryError,
+    },
+    leader::Leadership,
+    proto::admin::{
+        plan_run, scheduler_admin_server::SchedulerAdmin, PlanRun, PlanSelector, PlanStatus,
+        PlanStatusList, RunList, RunListRequest,
+    },
+};
+
+/// Maximum number of plan runs returned by `ListRuns`.
+const MAX_RUNS: i32 = 100;
+
+/// Lets operators control scraping plans at runtime.
+///
+/// Plans are run by the leader only, so they can be triggered, paused or resumed only
+/// through the leader replica.
+#[derive(Debug, Clone)]
+pub struct Admin {
+    /// Runtime control state of scraping plans.
+    control: Control,
+
+    /// Tells whether plans are run by this replica.
+    leadership: Leadership,
+
+    /// Database access layer to persist plan state.
+    plan_states: PlanStates,
+
+    /// Database access layer to access plan execution history.
+    plan_runs: PlanRuns,
+}
+
+// MARK: impl Admin
+
+impl Admin {
+    /// Creates new service instance.
+    pub fn new(
+        control: Control,
+        leadership: Leadership,
+        plan_states: PlanStates,
+        plan_runs: PlanRuns,
+    ) -> Self {
+        Admin {
+            control,
+            leadership,
+            plan_states,
+            plan_runs,
+        }
+    }
+
+    /// Returns sources of selected plans. Unknown source selects all plans.
+    fn select(&self, source: i32) -> Result<Vec<Source>, Status> {
+        if source == 0 {
+            return Ok(self.control.sources());
+        }
+
+        let source = Source::try_from(source)
+            .map_err(|_| Status::invalid_argument("unsupported anime source"))?;
+        if !self.control.sources().contains(&source) {
+            return Err(Status::not_found("plan for the source is not running"));
+        }
+
+        Ok(vec![source])
+    }
+
+    /// Fails if plans are not run by this replica.
+    fn check_leader(&self) -> Result<(), Status> {
+        if self.leadership.is_leader() {
+            return Ok(());
+        }
+
+        Err(Status::failed_precondition(
+            "plans are run by the leader, this replica is a standby",
+        ))
+    }
+
+    /// Pauses or resumes selected plans and persists their state.
+    async fn set_paused(&self, selector: PlanSelector, paused: bool) -> Result<(), Status> {
+        self.check_leader()?;
+        let sources = self.select(selector.source)?;
+        let (plan_states, persisted) = (self.plan_states.clone(), sources.clone());
+        task::spawn_blocking(move || {
+            for source in persisted {
+                plan_states.set_paused(source, paused)?;
+            }
+
+            Ok::<_, QueryError>(())
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(storage_status)?;
+
+        for source in sources {
+            info!("setting plan for {:?} paused: {}", source, paused);
+            self.control.set_paused(source, paused);
+        }
+
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl SchedulerAdmin for Admin {
+    async fn trigger_run(&self, request: Request<PlanSelector>) -> Result<Response<()>, Status> {
+        self.check_leader()?;
+        let sources = self.select(request.into_inner().source)?;
+        if let Some(source) = sources.iter().find(|s| self.control.is_paused(**s)) {
+            return Err(Status::failed_precondition(format!(
+                "plan for {:?} is paused",
+                source
+            )));
+        }
+
+        for source in sources {
+            info!("triggering plan run for {:?}", source);
+            self.control.trigger(source);
+        }
+
+        Ok(Response::new(()))
+    }
+
+    async fn pause(&self, request: Request<PlanSelector>) -> Result<Response<()>, Status> {
+        self.set_paused(request.into_inner(), true).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn resume(&self, request: Request<PlanSelector>) -> Result<Response<()>, Status> {
+        self.set_paused(request.into_inner(), false).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn get_status(
+        &self,
+        request: Request<PlanSelector>,
+    ) -> Result<Response<PlanStatusList>, Status> {
+        let sources = self.select(request.into_inner().source)?;
+        let (plan_states, plan_runs) = (self.plan_states.clone(), self.plan_runs.clone());
+        let persisted = task::spawn_blocking(move || {
+            let mut persisted = vec![];
+            for source in sources {
+                let state = plan_states.get(source)?;
+                let last_success = plan_runs.latest_succeeded(source)?;
+                persisted.push((state, last_success));
+            }
+
+            Ok::<_, QueryError>(persisted)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(storage_status)?;
+
+        let plans = persisted
+            .into_iter()
+            .map(|(state, last_success)| {
+                let status = self.control.status(state.source).unwrap_or_default();
+                PlanStatus {
+                    source: state.source as i32,
+                    paused: self.control.is_paused(state.source),
+                    running: status.running,
+                    degraded: state.degraded,
+                    consecutive_failures: state.consecutive_failures,
+                    last_error: state.last_error.unwrap_or_default(),
+                    next_run_at: timestamp(status.next_run_at),
+                    last_success_at: timestamp(
+                        last_success.map(|r| r.finished_at.unwrap_or(r.started_at)),
+                    ),
+                }
+            })
+            .collect();
+
+        Ok(Response::new(PlanStatusList { plans }))
+    }
+
+    async fn list_runs(
+        &self,
+        request: Request<RunListRequest>,
+    ) -> Result<Response<RunList>, Status> {
+        let req = request.into_inner();
+        let source = match req.source {
+            0 => None,
+            source => Some(
+                Source::try_from(source)
+                    .map_err(|_| Status::invalid_argument("unsupported anime source"))?,
+            ),
+        };
+
+        let limit = match req.limit {
+            limit if limit <= 0 => MAX_RUNS,
+            limit => limit.min(MAX_RUNS),
+        };
+
+        let plan_runs = self.plan_runs.clone();
+        let runs = task::spawn_blocking(move || plan_runs.list(source, limit as i64))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(storage_status)?;
+
+        let runs = runs.into_iter().map(PlanRun::from).collect();
+        Ok(Response::new(RunList { runs }))
+    }
+}
+
+/// Returns unix timestamp of the date or 0 if there's no date.
+fn timestamp(date: Option<DateTime<Utc>>) -> i64 {
+    date.map_or(0, |d| d.timestamp())
+}
+
+/// Maps database error to RPC status.
+fn storage_status(e: QueryError) -> Status {
+    error!("failed to access plan state: {}", e);
+    Status::internal(e.to_string())
+}
+
+// MARK: impl PlanRun
+
+impl From<entity::PlanRun> for PlanRun {
+    fn from(run: entity::PlanRun) -> Self {
+        let phase = match run.phase {
+            Phase::UpdateIndex => plan_run::Phase::UpdateIndex,
+            Phase::ImportIndex => plan_run::Phase::ImportIndex,
+            Phase::ScrapeData => plan_run::Phase::ScrapeData,
+        };
+
+        let outcome = match run.outcome {
+            Outcome::Running => plan_run::Outcome::Running,
+            Outcome::Succeeded => plan_run::Outcome::Succeeded,
+            Outcome::Failed => plan_run::Outcome::Failed,
+            Outcome::Interrupted => plan_run::Outcome::Interrupted,
+        };
+
+        PlanRun {
+            id: Some(run.id),
+            source: run.source as i32,
+            phase: phase as i32,
+            outcome: outcome as i32,
+            error: run.error.unwrap_or_default(),
+            started_at: run.started_at.timestamp(),
+            finished_at: timestamp(run.finished_at),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use tokio::task;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+use std::{convert::TryFrom, time::Duration as StdDuration};
+
+use crate::{
+    db::{entity::Source, tasks::ScrapeTasks, QueryError, UnderlyingError},
+    proto::{
+        data::Anime,
+        scraping::{
+            scraper_tasks_service_server::ScraperTasksService, Job, Task, TaskCreate, TaskFinish,
+            TaskYield,
+        },
+        uuid::Uuid,
+    },
+};
+
+/// Hands out anime titles that are due for scraping to scraper services.
+#[derive(Debug, Clone)]
+pub struct ScraperTasks {
+    /// Database access layer for scraping tasks.
+    tasks: ScrapeTasks,
+
+    /// How long jobs of a task are reserved for a scraper without progress.
+    lease: StdDuration,
+}
+
+// MARK: impl ScraperTasks
+
+impl ScraperTasks {
+    /// Creates new service instance.
+    pub fn new(tasks: ScrapeTasks, lease: StdDuration) -> Self {
+        ScraperTasks { tasks, lease }
+    }
+}
+
+#[tonic::async_trait]
+impl ScraperTasksService for ScraperTasks {
+    async fn create_task(&self, request: Request<TaskCreate>) -> Result<Response<Task>, Status> {
+        let req = request.into_inner();
+        let source = Source::try_from(req.source)
+            .map_err(|_| Status::invalid_argument("unsupported anime source"))?;
+        if req.limit <= 0 {
+            return Err(Status::invalid_argument("limit should be positive"));
+        }
+
+        let (tasks, lease) = (self.tasks.clone(), self.lease);
+        let (task, jobs) = task::spawn_blocking(move || tasks.create(source, req.limit, lease))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(storage_status)?;
+
+        info!(
+            "created scraping task {} with {} jobs",
+            &task.id,
+            jobs.len()
+        );
+        let jobs = jobs
+            .into_iter()
+            .map(|j| Job {
+                id: Some(j.id),
+                anime_id: j.anime_id,
+            })
+            .collect();
+
+        Ok(Response::new(Task {
+            id: Some(task.id),
+            source: req.source,
+            jobs,
+        }))
+    }
+
+    /// Completes the job and schedules next update of it's anime title.
+    ///
+    /// The scheduler doesn't store anime data, so only air dates of the scraped anime
+    /// are used to pick the next update date and the rest of it is discarded.
+    async fn yield_result(&self, request: Request<TaskYield>) -> Result<Response<()>, Status> {
+        let req = request.into_inner();
+        let task_id = required(req.task_id, "task_id")?;
+        let job_id = required(req.job_id, "job_id")?;
+        let next_update = next_update(req.anime.as_ref(), Utc::now());
+
+        let tasks = self.tasks.clone();
+        let schedule =
+            task::spawn_blocking(move || tasks.complete_job(&task_id, &job_id, next_update))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .map_err(storage_status)?;
+
+        info!(
+            "anime {} scraped, next update at {}",
+            schedule.anime_id, schedule.next_update_at
+        );
+        Ok(Response::new(()))
+    }
+
+    async fn complete_task(&self, request: Request<TaskFinish>) -> Result<Response<()>, Status> {
+        let req = request.into_inner();
+        let task_id = required(req.task_id, "task_id")?;
+
+        let tasks = self.tasks.clone();
+        let id = task_id.clone();
+        let task = task::spawn_blocking(move || tasks.finish(&id))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(storage_status)?;
+
+        match task {
+            Some(task) => info!("scraping task finished: {}", &task.id),
+            None => info!("scraping task without jobs finished: {}", &task_id),
+        }
+
+        Ok(Response::new(()))
+    }
+}
+
+/// Returns date when an anime title should be scraped again.
+///
+/// Currently airing titles are updated daily, recently finished or not yet aired
+/// titles weekly and the rest monthly. If the title has not been scraped it will
+/// be retried the next day, as well as the title with air dates out of range.
+fn next_update(anime: Option<&Anime>, now: DateTime<Utc>) -> DateTime<Utc> {
+    let anime = match anime {
+        Some(anime) => anime,
+        None => return now + Duration::days(1),
+    };
+
+    let (start, end) = match (date(anime.start_date), date(anime.end_date)) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return now + Duration::days(1),
+    };
+
+    let delay = if anime.start_date == 0 || start > now {
+        Duration::weeks(1)
+    } else if anime.end_date == 0 || end > now {
+        Duration::days(1)
+    } else if now - end < Duration::days(90) {
+        Duration::weeks(1)
+    } else {
+        Duration::days(30)
+    };
+
+    now + delay
+}
+
+/// Returns date of the unix timestamp or `None` if it's out of range.
+fn date(timestamp: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(timestamp, 0).single()
+}
+
+/// Returns value of a required request field.
+fn required(id: Option<Uuid>, field: &str) -> Result<Uuid, Status> {
+    id.ok_or_else(|| Status::invalid_argument(format!("missing {}", field)))
+}
+
+/// Maps database error to RPC status.
+fn storage_status(e: QueryError) -> Status {
+    match e {
+        QueryError::QueryFailed(UnderlyingError::NotFound) => {
+            Status::not_found("no such task or job")
+        }
+        e => {
+            error!("failed to access scraping tasks: {}", e);
+            Status::internal(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::next_update;
+    use crate::proto::data::Anime;
+
+    fn anime(start: i64, end: i64) -> Anime {
+        Anime {
+            start_date: start,
+            end_date: end,
+            ..Anime::default()
+        }
+    }
+
+    #[test]
+    fn test_next_update() {
+        let now = Utc.ymd(2020, 4, 10).and_hms(0, 0, 0);
+        let day = Duration::days(1).num_seconds();
+        let ts = now.timestamp();
+
+        let airing = anime(ts - 30 * day, 0);
+        assert_eq!(next_update(Some(&airing), now), now + Duration::days(1));
+
+        let announced = anime(ts + 30 * day, 0);
+        assert_eq!(next_update(Some(&announced), now), now + Duration::weeks(1));
+
+        let finished = anime(ts - 900 * day, ts - 800 * day);
+        assert_eq!(next_update(Some(&finished), now), now + Duration::days(30));
+
+        assert_eq!(next_update(None, now), now + Duration::days(1));
+
+        let invalid = anime(i64::MAX, i64::MAX);
+        assert_eq!(next_update(Some(&invalid), now), now + Duration::days(1));
+
+        let invalid_end = anime(ts - 30 * day, i64::MAX);
+        assert_eq!(
+            next_update(Some(&invalid_end), now),
+            now + Duration::days(1)
+        );
+    }
+}
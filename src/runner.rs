@@ -0,0 +1,401 @@
+use chrono::{DateTime, Duration, Utc};
+use tokio::{task, time};
+use tracing::{error, info, warn};
+use tracing_futures::Instrument;
+
+use std::{sync::Arc, time::Duration as StdDuration};
+
+use crate::{
+    control::Control,
+    db::{
+        entity::{PlanState, Source},
+        import::FailedImports,
+        index::IndexFiles,
+        intents::{ImportIntents, ScrapeIntents},
+        repo::Repositories,
+        runs::PlanRuns,
+        state::PlanStates,
+        ConnectionPool, QueryError,
+    },
+    health::Liveness,
+    leader::Leadership,
+    metrics,
+    plan::{
+        retry::{Decision, RetryPolicy},
+        Clients, PlanError, ScrapePlan,
+    },
+    settings::{Schedule, Settings, SourceConfig},
+    shutdown::Shutdown,
+};
+
+/// Periodically runs scraping plan for a single anime source.
+#[derive(Debug)]
+pub struct PlanRunner {
+    /// Anime source to scrape.
+    source: Source,
+
+    /// Clients of external services of the source reused between runs.
+    clients: Clients,
+
+    /// Schedule of plan runs.
+    schedule: Schedule,
+
+    /// How long to wait for running plan to finish on shutdown.
+    grace_period: StdDuration,
+
+    /// Database access layer to access processed or pending index files.
+    index_files: IndexFiles,
+
+    /// Database access layer to access failed to parse anime entries.
+    failed_imports: FailedImports,
+
+    /// Database access layer to record intents sent to importer service.
+    import_intents: ImportIntents,
+
+    /// Database access layer to record intents sent to scraping service.
+    scrape_intents: ScrapeIntents,
+
+    /// Database access layer to record plan execution history.
+    plan_runs: PlanRuns,
+
+    /// Database access layer to persist plan state.
+    plan_states: PlanStates,
+
+    /// Progress tracker for liveness checks.
+    liveness: Liveness,
+
+    /// Runtime control state shared with admin API.
+    control: Control,
+}
+
+// MARK: impl PlanRunner
+
+impl PlanRunner {
+    /// Creates new runner instance for the anime `source`.
+    pub fn new(
+        config: &Settings,
+        source: &SourceConfig,
+        pool: ConnectionPool,
+        clients: Clients,
+        liveness: Liveness,
+        control: Control,
+    ) -> Self {
+        PlanRunner {
+            source: source.name(),
+            clients,
+            schedule: config.schedule().clone(),
+            grace_period: config.shutdown().grace_period(),
+            index_files: IndexFiles::new(pool.clone()),
+            failed_imports: FailedImports::new(pool.clone()),
+            import_intents: ImportIntents::new(pool.clone()),
+            scrape_intents: ScrapeIntents::new(pool.clone()),
+            plan_runs: PlanRuns::new(pool.clone()),
+            plan_states: PlanStates::new(pool),
+            liveness,
+            control,
+        }
+    }
+
+    /// Runs scraping plan according to the schedule until shutdown is requested.
+    ///
+    /// The plan is run only while this replica is the leader. Failed runs are retried
+    /// according to retry policy. On shutdown currently running plan is given a grace
+    /// period to finish and is recorded as interrupted otherwise. If leadership is lost,
+    /// running plan is interrupted immediately since another replica is about to take
+    /// over. Runs can be triggered earlier or paused using shared control state.
+    pub async fn run(self, mut shutdown: Shutdown, mut leadership: Leadership) {
+        // fails liveness check if the plan crashes
+        let guard = self.liveness.guard(self.source);
+        while !shutdown.is_requested() {
+            if leadership.is_leader() {
+                self.lead(&mut shutdown, &mut leadership)
+                    .in_current_span()
+                    .await;
+                continue;
+            }
+
+            info!("waiting to become leader");
+            tokio::select! {
+                _ = leadership.acquired() => {}
+                _ = shutdown.wait() => {}
+            }
+        }
+
+        guard.stopped();
+        info!("scraping plan stopped");
+    }
+
+    /// Runs scraping plan according to the schedule until shutdown is requested or
+    /// leadership is lost.
+    async fn lead(&self, shutdown: &mut Shutdown, leadership: &mut Leadership) {
+        // only the leader runs plans, so running ones are left by a stopped replica
+        self.interrupt("scheduler has been stopped unexpectedly")
+            .in_current_span()
+            .await;
+        self.recover(shutdown, leadership).in_current_span().await;
+
+        let failures = self.load_failures().in_current_span().await;
+        self.load_last_success().in_current_span().await;
+        let mut retry = RetryPolicy::new(&self.schedule, failures);
+
+        while !shutdown.is_requested() && leadership.is_leader() {
+            if self.control.is_paused(self.source) {
+                info!("plan is paused, waiting to be resumed");
+                self.control.set_next_run(self.source, None);
+                tokio::select! {
+                    _ = self.control.resumed(self.source) => info!("plan resumed"),
+                    _ = shutdown.wait() => {}
+                    _ = leadership.lost() => {}
+                }
+
+                continue;
+            }
+
+            self.liveness.started(self.source);
+            self.control.set_running(self.source, true);
+            let res = self.run_plan(shutdown, leadership).in_current_span().await;
+            self.control.set_running(self.source, false);
+            self.liveness.finished(self.source);
+
+            let res = match res {
+                Some(res) => res,
+                None if !leadership.is_leader() => {
+                    // another replica may be running the plan already, so the run is left
+                    // for the next leader to mark as interrupted when it takes over
+                    warn!("leadership has been lost, plan has been stopped");
+                    break;
+                }
+                None => {
+                    warn!("plan has not finished in time, interrupting");
+                    self.interrupt("scheduler has been shut down")
+                        .in_current_span()
+                        .await;
+                    break;
+                }
+            };
+
+            let next = self.process_result(res, &mut retry).in_current_span().await;
+            self.update_backlog().in_current_span().await;
+            self.control.set_next_run(self.source, Some(next));
+            tokio::select! {
+                _ = delay_until(next) => {}
+                _ = self.control.triggered(self.source) => info!("plan run triggered"),
+                _ = shutdown.wait() => {}
+                _ = leadership.lost() => {}
+            }
+        }
+
+        self.control.set_next_run(self.source, None);
+    }
+
+    /// Runs scraping plan once.
+    ///
+    /// Returns `None` if leadership has been lost or if shutdown has been requested
+    /// and the plan didn't finish within grace period.
+    async fn run_plan(
+        &self,
+        shutdown: &mut Shutdown,
+        leadership: &mut Leadership,
+    ) -> Option<Result<bool, PlanError>> {
+        info!("running scraping plan");
+        let plan = self.plan();
+        let run = plan.run().in_current_span();
+        tokio::pin!(run);
+
+        tokio::select! {
+            res = &mut run => return Some(res),
+            _ = leadership.lost() => return None,
+            _ = shutdown.wait() => {}
+        };
+
+        info!(
+            "shutdown requested, waiting {:?} for plan to finish",
+            self.grace_period
+        );
+        tokio::select! {
+            res = time::timeout(self.grace_period, run) => res.ok(),
+            _ = leadership.lost() => None,
+        }
+    }
+
+    /// Reconciles intents that a stopped replica has sent and never completed.
+    ///
+    /// Intents that are left outstanding if it's interrupted are reconciled by the next
+    /// leader.
+    async fn recover(&self, shutdown: &mut Shutdown, leadership: &mut Leadership) {
+        let plan = self.plan();
+        tokio::select! {
+            res = plan.recover(self.schedule.recovery()).in_current_span() => match res {
+                Ok(0) => {}
+                Ok(count) => warn!("reconciled {} outstanding intents", count),
+                Err(e) => error!("failed to reconcile outstanding intents: {}", e),
+            },
+            _ = leadership.lost() => warn!("leadership has been lost, stopping reconciliation"),
+            _ = shutdown.wait() => warn!("shutdown requested, stopping reconciliation"),
+        }
+    }
+
+    /// Marks all running plans of the source as interrupted.
+    async fn interrupt(&self, reason: &'static str) {
+        let (plan_runs, source) = (self.plan_runs.clone(), self.source);
+        match task::spawn_blocking(move || plan_runs.interrupt_running(source, reason)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(count)) => warn!("marked {} plan runs as interrupted", count),
+            Ok(Err(e)) => error!("failed to mark plan runs as interrupted: {}", e),
+            Err(e) => error!("failed to mark plan runs as interrupted: {}", e),
+        }
+    }
+
+    /// Returns new scraping plan instance.
+    fn plan(&self) -> ScrapePlan {
+        ScrapePlan::new(
+            self.source,
+            self.clients.clone(),
+            Repositories {
+                index_files: Arc::new(self.index_files.clone()),
+                failed_imports: Arc::new(self.failed_imports.clone()),
+                import_intents: Arc::new(self.import_intents.clone()),
+                scrape_intents: Arc::new(self.scrape_intents.clone()),
+                plan_runs: Arc::new(self.plan_runs.clone()),
+            },
+            self.schedule.max_import_attempts(),
+            self.schedule.max_title_attempts(),
+        )
+    }
+
+    /// Restores persisted pause flag and returns number of consecutive plan failures
+    /// before the app has been started.
+    async fn load_failures(&self) -> u32 {
+        let (plan_states, source) = (self.plan_states.clone(), self.source);
+        let state = match task::spawn_blocking(move || plan_states.get(source)).await {
+            Ok(Ok(state)) => state,
+            Ok(Err(e)) => {
+                error!("failed to load plan state: {}", e);
+                return 0;
+            }
+            Err(e) => {
+                error!("failed to load plan state: {}", e);
+                return 0;
+            }
+        };
+
+        self.control.set_paused(self.source, state.paused);
+        if state.degraded {
+            warn!(
+                "plan is degraded since {:?}, last error: {:?}",
+                state.degraded_at, state.last_error
+            );
+        }
+
+        state.consecutive_failures as u32
+    }
+
+    /// Exports time of the last successful plan run before the app has been started.
+    async fn load_last_success(&self) {
+        let (plan_runs, source) = (self.plan_runs.clone(), self.source);
+        match task::spawn_blocking(move || plan_runs.latest_succeeded(source)).await {
+            Ok(Ok(Some(run))) => {
+                metrics::set_last_success(source, run.finished_at.unwrap_or(run.started_at))
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => error!("failed to load last successful plan run: {}", e),
+            Err(e) => error!("failed to load last successful plan run: {}", e),
+        }
+    }
+
+    /// Exports number of index files and titles waiting to be imported.
+    async fn update_backlog(&self) {
+        let (index_files, failed_imports) = (self.index_files.clone(), self.failed_imports.clone());
+        let source = self.source;
+        let res = task::spawn_blocking(move || -> Result<_, QueryError> {
+            let pending = index_files.count_pending(source)?;
+            let (failed, quarantined) = failed_imports.count_titles(source)?;
+            Ok((pending, failed, quarantined))
+        })
+        .await;
+
+        match res {
+            Ok(Ok((pending, failed, quarantined))) => {
+                metrics::set_backlog(source, pending, failed, quarantined)
+            }
+            Ok(Err(e)) => error!("failed to count pending imports: {}", e),
+            Err(e) => error!("failed to count pending imports: {}", e),
+        }
+    }
+
+    /// Persists plan state after it's run and returns time of the next run.
+    async fn process_result(
+        &self,
+        res: Result<bool, PlanError>,
+        retry: &mut RetryPolicy,
+    ) -> DateTime<Utc> {
+        let (plan_states, source) = (self.plan_states.clone(), self.source);
+        match res {
+            Ok(more) => {
+                info!("scrape succeeded, has more data to srape: {}", more);
+                metrics::plan_succeeded(source, more, Utc::now());
+                if retry.failures() > 0 {
+                    retry.succeeded();
+                    persist(move || plan_states.recover(source)).await;
+                }
+
+                if more {
+                    return Utc::now();
+                }
+
+                let next = self.schedule.next_run(Utc::now());
+                info!("nothing to scrape anymore, next run at {}", next);
+                next
+            }
+            Err(e) => {
+                error!("scraping plan failed: {}", e.report());
+                metrics::plan_failed(source, &e);
+                let decision = retry.failed(&e);
+                let failures = retry.failures();
+                let err = e.report().to_string();
+                persist(move || {
+                    let state = plan_states.failed(source, failures as i32, &err)?;
+                    match decision {
+                        Decision::Degrade => plan_states.degrade(source),
+                        Decision::Retry(_) => Ok(state),
+                    }
+                })
+                .await;
+
+                match decision {
+                    Decision::Retry(delay) => {
+                        info!("retrying failed plan in {:?}", delay);
+                        Utc::now() + Duration::from_std(delay).unwrap_or_else(|_| Duration::zero())
+                    }
+                    Decision::Degrade => {
+                        let next = self.schedule.next_run(Utc::now());
+                        error!(
+                            "plan degraded after {} consecutive failures, next run at {}",
+                            failures, next
+                        );
+                        next
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Saves plan state to the database and logs an error if it fails.
+async fn persist<F>(f: F)
+where
+    F: FnOnce() -> Result<PlanState, QueryError> + Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => error!("failed to save plan state: {}", e),
+        Err(e) => error!("failed to save plan state: {}", e),
+    }
+}
+
+/// Waits until specified date. Returns immediately if the date is in the past.
+async fn delay_until(date: DateTime<Utc>) {
+    if let Ok(delay) = (date - Utc::now()).to_std() {
+        time::delay_for(delay).await;
+    }
+}
@@ -1,17 +1,27 @@
 mod template;
 
+use chrono::{DateTime, Utc};
 use config::{Config, ConfigError, File, FileFormat};
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 
-use std::time::Duration;
+use std::{collections::HashSet, net::SocketAddr, str::FromStr, time::Duration};
 
 use template::TemplateConfig;
 
+use crate::db::entity::Source;
+
 /// App settings used to configure it's state
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
     services: Service,
+    sources: Vec<SourceConfig>,
     db: Db,
+    schedule: Schedule,
+    shutdown: Shutdown,
+    rpc: Rpc,
+    http: Http,
+    health: Health,
+    leader: Leader,
 }
 
 /// Database configuration
@@ -30,12 +40,103 @@ pub struct Service {
     scraper: RemoteServiceConfig,
 }
 
-/// Remote gRPC service configuration
+/// Anime source configuration
+///
+/// Services that are not overridden for the source are taken from global services configuration
 #[derive(Debug, Clone, Deserialize)]
+pub struct SourceConfig {
+    #[serde(deserialize_with = "deserialize_source")]
+    name: Source,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    indexer: Option<RemoteServiceConfig>,
+    import: Option<RemoteServiceConfig>,
+    scraper: Option<RemoteServiceConfig>,
+}
+
+/// Remote gRPC service configuration
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
 pub struct RemoteServiceConfig {
     url: String,
-    connection_timeout: Option<i32>,
-    request_timeout: Option<i32>,
+    #[serde(default)]
+    endpoints: Vec<String>,
+    #[serde(default)]
+    discovery: Discovery,
+    connection_timeout: Option<u64>,
+    request_timeout: Option<u64>,
+    tcp_keepalive: Option<u64>,
+}
+
+/// How to find instances of a remote service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Discovery {
+    /// Service URL and additional endpoints from configuration are used as is
+    Static,
+
+    /// Every address the service URL host resolves to is a separate instance
+    Dns,
+}
+
+/// Scraping plan schedule configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schedule {
+    #[serde(default, deserialize_with = "deserialize_cron")]
+    cron: Option<cron::Schedule>,
+    interval: u64,
+    retry_interval: u64,
+    max_retry_interval: u64,
+    max_failures: u32,
+    max_import_attempts: u32,
+    max_title_attempts: u32,
+    #[serde(default)]
+    recovery: Recovery,
+}
+
+/// What to do with intents that were sent before the app has stopped and never completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recovery {
+    /// Intents are sent again with the same ID
+    Reissue,
+
+    /// Intents are marked as abandoned and the work is left to the next plan run
+    Abandon,
+}
+
+/// Graceful shutdown configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct Shutdown {
+    grace_period: u64,
+}
+
+/// Configuration of gRPC services hosted by the app
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rpc {
+    address: SocketAddr,
+    task_lease: u64,
+}
+
+/// Configuration of HTTP server with service endpoints like metrics
+#[derive(Debug, Clone, Deserialize)]
+pub struct Http {
+    address: SocketAddr,
+}
+
+/// Health and readiness checks configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct Health {
+    stall_timeout: u64,
+    check_timeout: u64,
+}
+
+/// Leader election between scheduler replicas configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct Leader {
+    election: bool,
+    lock_key: i64,
+    renew_interval: u64,
+    lease: u64,
 }
 
 // MARK: impl Settings
@@ -50,16 +151,57 @@ impl Settings {
 
         let mut s = Config::new();
         s.merge(config)?;
-        s.try_into()
+        let settings: Settings = s.try_into()?;
+        check_sources(&settings.sources)?;
+        Ok(settings)
     }
 
     pub fn services(&self) -> &Service {
         &self.services
     }
 
+    /// Returns configuration of anime sources that should be scraped
+    pub fn enabled_sources(&self) -> impl Iterator<Item = &SourceConfig> {
+        self.sources.iter().filter(|s| s.enabled)
+    }
+
+    /// Returns configuration of all known anime sources
+    pub fn sources(&self) -> &[SourceConfig] {
+        &self.sources
+    }
+
+    /// Returns configuration of the anime source even if it's disabled
+    pub fn source(&self, name: Source) -> Option<&SourceConfig> {
+        self.sources.iter().find(|s| s.name == name)
+    }
+
     pub fn db(&self) -> &Db {
         &self.db
     }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    pub fn rpc(&self) -> &Rpc {
+        &self.rpc
+    }
+
+    pub fn http(&self) -> &Http {
+        &self.http
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    pub fn leader(&self) -> &Leader {
+        &self.leader
+    }
 }
 
 // MARK: impl Db
@@ -100,6 +242,68 @@ impl Service {
     }
 }
 
+// MARK: impl SourceConfig
+
+impl SourceConfig {
+    /// Returns anime source
+    pub fn name(&self) -> Source {
+        self.name
+    }
+
+    /// Returns services configuration for the source
+    pub fn services(&self, defaults: &Service) -> Service {
+        let or_default = |s: &Option<RemoteServiceConfig>, d: &RemoteServiceConfig| {
+            s.as_ref().unwrap_or(d).clone()
+        };
+
+        Service {
+            indexer: or_default(&self.indexer, &defaults.indexer),
+            import: or_default(&self.import, &defaults.import),
+            scraper: or_default(&self.scraper, &defaults.scraper),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn deserialize_source<'de, D>(deserializer: D) -> Result<Source, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    const SOURCES: &[&str] = &["anidb", "mal", "ann"];
+
+    let name = String::deserialize(deserializer)?;
+    parse_source(&name).ok_or_else(|| de::Error::unknown_variant(&name, SOURCES))
+}
+
+/// Makes sure that every anime source is configured only once
+fn check_sources(sources: &[SourceConfig]) -> Result<(), ConfigError> {
+    let mut seen = HashSet::new();
+    for source in sources {
+        if !seen.insert(source.name) {
+            let msg = format!(
+                "anime source {:?} is configured more than once",
+                source.name
+            );
+            return Err(ConfigError::Message(msg));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns anime source by it's name used in configuration
+pub fn parse_source(name: &str) -> Option<Source> {
+    match name {
+        "anidb" => Some(Source::Anidb),
+        "mal" => Some(Source::Mal),
+        "ann" => Some(Source::Ann),
+        _ => None,
+    }
+}
+
 // MARK: impl RemoteServiceConfig
 
 impl RemoteServiceConfig {
@@ -108,22 +312,249 @@ impl RemoteServiceConfig {
         &self.url
     }
 
+    /// Returns URLs of all statically configured service instances
+    pub fn endpoints(&self) -> Vec<String> {
+        let mut endpoints = vec![self.url.clone()];
+        endpoints.extend(self.endpoints.iter().cloned());
+        endpoints
+    }
+
+    /// Returns how to find service instances
+    pub fn discovery(&self) -> Discovery {
+        self.discovery
+    }
+
+    /// Returns configuration of a single service instance at `url`
+    pub fn with_url(&self, url: &str) -> Self {
+        RemoteServiceConfig {
+            url: url.to_string(),
+            endpoints: vec![],
+            discovery: Discovery::Static,
+            ..self.clone()
+        }
+    }
+
     /// Returns preferred connection timeout
-    pub fn connection_timeout(&self) -> Option<i32> {
-        self.connection_timeout
+    pub fn connection_timeout(&self) -> Option<Duration> {
+        self.connection_timeout.map(|t| Duration::new(t, 0))
     }
 
     /// Returns preferred request timeout
-    pub fn request_timeout(&self) -> Option<i32> {
-        self.request_timeout
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout.map(|t| Duration::new(t, 0))
     }
+
+    /// Returns interval of TCP keepalive probes sent over idle connections
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.tcp_keepalive.map(|t| Duration::new(t, 0))
+    }
+}
+
+// MARK: impl Recovery
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Recovery::Reissue
+    }
+}
+
+// MARK: impl Discovery
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Discovery::Static
+    }
+}
+
+// MARK: impl Schedule
+
+impl Schedule {
+    /// Returns time of the next full plan run after provided date
+    ///
+    /// Cron expression is used if it's set, otherwise the run is scheduled
+    /// after configured interval.
+    pub fn next_run(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        let upcoming = self.cron.as_ref().and_then(|c| c.after(&after).next());
+        match upcoming {
+            Some(next) => next,
+            None => after + chrono::Duration::seconds(self.interval as i64),
+        }
+    }
+
+    /// Returns initial delay before retrying failed plan
+    pub fn retry_interval(&self) -> Duration {
+        Duration::new(self.retry_interval, 0)
+    }
+
+    /// Returns maximum delay before retrying failed plan
+    pub fn max_retry_interval(&self) -> Duration {
+        Duration::new(self.max_retry_interval, 0)
+    }
+
+    /// Returns number of consecutive failures after which the plan is considered degraded
+    pub fn max_failures(&self) -> u32 {
+        self.max_failures
+    }
+
+    /// Returns how many times to try importing an index file before skipping it
+    pub fn max_import_attempts(&self) -> u32 {
+        self.max_import_attempts
+    }
+
+    /// Returns how many times an anime title may fail to import before it's quarantined
+    pub fn max_title_attempts(&self) -> u32 {
+        self.max_title_attempts
+    }
+
+    /// Returns what to do with intents that never completed
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+}
+
+// MARK: impl Shutdown
+
+impl Shutdown {
+    /// Returns how long to wait for running scraping plans to finish on shutdown
+    pub fn grace_period(&self) -> Duration {
+        Duration::new(self.grace_period, 0)
+    }
+}
+
+// MARK: impl Rpc
+
+impl Rpc {
+    /// Returns address to listen for gRPC requests on
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns how long jobs of a scraping task are reserved for a scraper without progress
+    pub fn task_lease(&self) -> Duration {
+        Duration::new(self.task_lease, 0)
+    }
+}
+
+// MARK: impl Http
+
+impl Http {
+    /// Returns address to listen for HTTP requests on
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+// MARK: impl Health
+
+impl Health {
+    /// Returns how long a plan may run before it's considered stuck
+    pub fn stall_timeout(&self) -> Duration {
+        Duration::new(self.stall_timeout, 0)
+    }
+
+    /// Returns how long to wait for external service to accept connection on readiness check
+    pub fn check_timeout(&self) -> Duration {
+        Duration::new(self.check_timeout, 0)
+    }
+}
+
+// MARK: impl Leader
+
+impl Leader {
+    /// Returns `true` if replicas should elect a leader to run scraping plans
+    pub fn election(&self) -> bool {
+        self.election
+    }
+
+    /// Returns key of PostgreSQL advisory lock held by the leader
+    pub fn lock_key(&self) -> i64 {
+        self.lock_key
+    }
+
+    /// Returns how often the leader renews it's lease and standbys try to take over
+    pub fn renew_interval(&self) -> Duration {
+        Duration::new(self.renew_interval, 0)
+    }
+
+    /// Returns how long the leader keeps leadership without successful renewal
+    pub fn lease(&self) -> Duration {
+        Duration::new(self.lease, 0)
+    }
+}
+
+fn deserialize_cron<'de, D>(deserializer: D) -> Result<Option<cron::Schedule>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = Option::<String>::deserialize(deserializer)?;
+    raw.map(|r| cron::Schedule::from_str(&r).map_err(de::Error::custom))
+        .transpose()
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use std::str::FromStr;
+
+    use super::{Recovery, Schedule, SourceConfig};
+
     #[test]
     fn test_parsing() {
         // if this does not panic then everything is good
         super::Settings::new().unwrap();
     }
+
+    #[test]
+    fn test_duplicate_sources() {
+        let sources: Vec<SourceConfig> = serde_json::from_value(serde_json::json!([
+            { "name": "anidb" },
+            { "name": "mal" },
+        ]))
+        .unwrap();
+        assert!(super::check_sources(&sources).is_ok());
+
+        let sources: Vec<SourceConfig> = serde_json::from_value(serde_json::json!([
+            { "name": "anidb" },
+            { "name": "anidb", "enabled": false },
+        ]))
+        .unwrap();
+        assert!(super::check_sources(&sources).is_err());
+    }
+
+    #[test]
+    fn test_cron_schedule() {
+        let schedule = Schedule {
+            cron: Some(cron::Schedule::from_str("0 30 4 * * *").unwrap()),
+            interval: 60,
+            retry_interval: 60,
+            max_retry_interval: 3600,
+            max_failures: 10,
+            max_import_attempts: 3,
+            max_title_attempts: 5,
+            recovery: Recovery::Reissue,
+        };
+
+        let now = Utc.ymd(2020, 4, 1).and_hms(12, 0, 0);
+        let next = Utc.ymd(2020, 4, 2).and_hms(4, 30, 0);
+        assert_eq!(schedule.next_run(now), next);
+    }
+
+    #[test]
+    fn test_interval_schedule() {
+        let schedule = Schedule {
+            cron: None,
+            interval: 3600,
+            retry_interval: 60,
+            max_retry_interval: 3600,
+            max_failures: 10,
+            max_import_attempts: 3,
+            max_title_attempts: 5,
+            recovery: Recovery::Reissue,
+        };
+
+        let now = Utc.ymd(2020, 4, 1).and_hms(12, 0, 0);
+        let next = Utc.ymd(2020, 4, 1).and_hms(13, 0, 0);
+        assert_eq!(schedule.next_run(now), next);
+    }
 }
@@ -0,0 +1,64 @@
+use futures::future;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+
+use std::io;
+
+/// Notifies listeners that the app is shutting down.
+#[derive(Debug)]
+pub struct Trigger(watch::Sender<bool>);
+
+/// Listens for app shutdown notification.
+#[derive(Debug, Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// Creates new shutdown notification channel.
+pub fn channel() -> (Trigger, Shutdown) {
+    let (tx, rx) = watch::channel(false);
+    (Trigger(tx), Shutdown(rx))
+}
+
+/// Waits until the app receives `SIGTERM` or `SIGINT` signal.
+pub async fn signal_received() -> io::Result<()> {
+    let mut term = signal(SignalKind::terminate())?;
+    let mut int = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = int.recv() => {}
+    }
+
+    Ok(())
+}
+
+// MARK: impl Trigger
+
+impl Trigger {
+    /// Notifies all listeners that the app is shutting down.
+    pub fn shutdown(self) {
+        // fails only if there's no listeners
+        let _ = self.0.broadcast(true);
+    }
+}
+
+// MARK: impl Shutdown
+
+impl Shutdown {
+    /// Returns `true` if shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until shutdown is requested.
+    ///
+    /// If the trigger is dropped without requesting shutdown the method will never return.
+    pub async fn wait(&mut self) {
+        while !self.is_requested() {
+            if self.0.recv().await.is_none() {
+                future::pending::<()>().await;
+            }
+        }
+    }
+}
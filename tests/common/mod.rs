@@ -0,0 +1,485 @@
+//! Test harness that runs scraping plans against in-process mocks of external services
+//! and a throwaway database schema.
+//!
+//! Database tests need `PG_TEST_DB_URL` environment variable and are ignored by default.
+
+#![allow(dead_code)]
+
+use diesel::{connection::SimpleConnection, Connection, PgConnection, RunQueryDsl};
+use hyper::{
+    header::HeaderValue,
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Request, Response, Server, StatusCode,
+};
+use serde_json::json;
+use tokio::{net::TcpStream, time};
+use tonic::Status;
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    fs,
+    net::{SocketAddr, TcpListener},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use satelit_scheduler::{
+    db::{self, entity::Source, repo::Repositories, ConnectionPool},
+    plan::{Clients, ScrapePlan},
+    proto::{
+        import::{
+            import_service_server::{ImportService, ImportServiceServer},
+            ImportIntent, ImportIntentResult,
+        },
+        scraping::{
+            scraper_service_server::{ScraperService, ScraperServiceServer},
+            ScrapeIntent, ScrapeIntentResult,
+        },
+    },
+    settings::{Db, Service},
+};
+
+/// Environment variable with URL of a database to create throwaway schemas in.
+const DB_URL_VAR: &str = "PG_TEST_DB_URL";
+
+/// How many times a title may fail to import before it's quarantined in test plans.
+pub const MAX_TITLE_ATTEMPTS: u32 = 2;
+
+/// Everything needed to run a scraping plan end to end.
+pub struct Harness {
+    /// Throwaway database schema.
+    pub db: TestDb,
+
+    /// Anime indexing service stub.
+    pub indexer: IndexerStub,
+
+    /// Importing service mock.
+    pub importer: MockImporter,
+
+    /// Scraping service mock.
+    pub scraper: MockScraper,
+
+    /// Configuration of external services pointing to the mocks.
+    pub services: Service,
+
+    /// Raw configuration `services` are parsed from.
+    config: serde_json::Value,
+}
+
+/// Database schema that is created for a single test and dropped afterwards.
+pub struct TestDb {
+    /// URL of the database the schema is created in.
+    url: String,
+
+    /// Name of the schema.
+    schema: String,
+
+    /// Connection pool which connections use the schema.
+    pool: ConnectionPool,
+}
+
+/// Canned HTTP response of the indexer stub.
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    /// Response status.
+    pub status: StatusCode,
+
+    /// Response headers.
+    pub headers: Vec<(&'static str, String)>,
+
+    /// Response body.
+    pub body: String,
+}
+
+/// HTTP server that replies with queued responses and records requests.
+#[derive(Debug, Clone)]
+pub struct IndexerStub {
+    /// Address the stub listens on.
+    addr: SocketAddr,
+
+    /// Responses to reply with, `404` is returned when there are none.
+    responses: Arc<Mutex<VecDeque<StubResponse>>>,
+
+    /// Path and headers of received requests.
+    requests: Arc<Mutex<Vec<(String, HeaderMap)>>>,
+}
+
+/// In-process importing service that replies with queued results.
+#[derive(Debug, Clone, Default)]
+pub struct MockImporter {
+    /// Results to reply with, empty successful result is returned when there are none.
+    results: Arc<Mutex<VecDeque<Result<Vec<i32>, Status>>>>,
+
+    /// Received import intents.
+    intents: Arc<Mutex<Vec<ImportIntent>>>,
+}
+
+/// In-process scraping service that replies with queued results.
+#[derive(Debug, Clone, Default)]
+pub struct MockScraper {
+    /// Results to reply with, `may_continue: false` is returned when there are none.
+    results: Arc<Mutex<VecDeque<Result<bool, Status>>>>,
+
+    /// Received scrape intents.
+    intents: Arc<Mutex<Vec<ScrapeIntent>>>,
+}
+
+// MARK: impl Harness
+
+impl Harness {
+    /// Starts all mocks and creates database schema.
+    ///
+    /// Panics if test database is not configured.
+    pub async fn new() -> Self {
+        let db = TestDb::new();
+        let indexer = IndexerStub::start();
+        let importer = MockImporter::default();
+        let scraper = MockScraper::default();
+
+        let import_addr = free_addr();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(ImportServiceServer::new(importer.clone()))
+                .serve(import_addr),
+        );
+
+        wait_listening(import_addr).await;
+        let scraper_addr = start_scraper(scraper.clone()).await;
+
+        let config = json!({
+            "indexer": service_config(indexer.addr),
+            "import": service_config(import_addr),
+            "scraper": service_config(scraper_addr),
+        });
+        let services = serde_json::from_value(config.clone()).expect("invalid services config");
+
+        Harness {
+            db,
+            indexer,
+            importer,
+            scraper,
+            services,
+            config,
+        }
+    }
+
+    /// Starts another scraping service mock and adds it to scraper endpoints.
+    pub async fn add_scraper(&mut self) -> MockScraper {
+        let scraper = MockScraper::default();
+        let addr = start_scraper(scraper.clone()).await;
+
+        let endpoints = &mut self.config["scraper"]["endpoints"];
+        if endpoints.is_null() {
+            *endpoints = json!([]);
+        }
+
+        endpoints
+            .as_array_mut()
+            .expect("invalid scraper endpoints")
+            .push(json!(format!("http://{}", addr)));
+        self.services =
+            serde_json::from_value(self.config.clone()).expect("invalid services config");
+
+        scraper
+    }
+
+    /// Returns new scraping plan for the `source` that uses the mocks.
+    pub fn plan(&self, source: Source, max_import_attempts: u32) -> ScrapePlan {
+        ScrapePlan::new(
+            source,
+            Clients::new(&self.services).expect("failed to create clients"),
+            Repositories::postgres(self.db.pool()),
+            max_import_attempts,
+            MAX_TITLE_ATTEMPTS,
+        )
+    }
+}
+
+// MARK: impl TestDb
+
+impl TestDb {
+    /// Creates new schema and applies all migrations to it.
+    ///
+    /// Panics if test database is not configured.
+    pub fn new() -> Self {
+        let url = std::env::var(DB_URL_VAR)
+            .unwrap_or_else(|_| panic!("{} is not set, can't run database test", DB_URL_VAR));
+
+        let schema = format!("test_{}", uuid::Uuid::new_v4().to_simple());
+        let conn = PgConnection::establish(&url).expect("failed to connect to test db");
+        diesel::sql_query(format!("create schema {}", &schema))
+            .execute(&conn)
+            .expect("failed to create test schema");
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let schema_url = format!(
+            "{}{}options=-c%20search_path%3D{}%2Cpublic",
+            &url, separator, &schema
+        );
+
+        let schema_conn =
+            PgConnection::establish(&schema_url).expect("failed to connect to test db");
+        migrate(&schema_conn);
+
+        let settings: Db = serde_json::from_value(json!({
+            "url": &schema_url,
+            "max_connections": 4,
+            "connection_timeout": 5,
+        }))
+        .expect("invalid db config");
+        let pool = db::new_connection_pool(&settings).expect("failed to create connection pool");
+
+        TestDb { url, schema, pool }
+    }
+
+    /// Returns connection pool which connections use the schema.
+    pub fn pool(&self) -> ConnectionPool {
+        self.pool.clone()
+    }
+
+    /// Returns URL of the database the schema is created in.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let conn = match PgConnection::establish(&self.url) {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("failed to drop test schema {}: {}", &self.schema, e);
+                return;
+            }
+        };
+
+        let query = format!("drop schema {} cascade", &self.schema);
+        if let Err(e) = diesel::sql_query(query).execute(&conn) {
+            eprintln!("failed to drop test schema {}: {}", &self.schema, e);
+        }
+    }
+}
+
+/// Applies all `up.sql` migrations in order.
+fn migrate(conn: &PgConnection) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+    let mut migrations: Vec<_> = fs::read_dir(dir)
+        .expect("failed to read migrations")
+        .map(|entry| entry.expect("failed to read migration").path())
+        .collect();
+    migrations.sort();
+
+    for migration in migrations {
+        let sql = fs::read_to_string(migration.join("up.sql")).expect("failed to read migration");
+        conn.batch_execute(&sql)
+            .unwrap_or_else(|e| panic!("failed to run {}: {}", migration.display(), e));
+    }
+}
+
+// MARK: impl StubResponse
+
+impl StubResponse {
+    /// Returns response with new index file info.
+    pub fn index(source: Source, file_path: &str, hash: &str) -> Self {
+        let body = json!({
+            "id": file_path,
+            "file_path": file_path,
+            "source": source as i32,
+            "hash": hash,
+        });
+
+        StubResponse {
+            status: StatusCode::OK,
+            headers: vec![("content-type", "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    /// Returns empty response with the `status`.
+    pub fn status(status: StatusCode) -> Self {
+        StubResponse {
+            status,
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+
+    /// Adds header to the response.
+    pub fn with_header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+// MARK: impl IndexerStub
+
+impl IndexerStub {
+    /// Starts the stub on a random port.
+    pub fn start() -> Self {
+        let responses = Arc::new(Mutex::new(VecDeque::new()));
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let (stub_responses, stub_requests) = (responses.clone(), requests.clone());
+        let make_svc = make_service_fn(move |_| {
+            let (responses, requests) = (stub_responses.clone(), stub_requests.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let (responses, requests) = (responses.clone(), requests.clone());
+                    async move {
+                        let path = req.uri().path().to_string();
+                        requests.lock().unwrap().push((path, req.headers().clone()));
+                        let resp = responses.lock().unwrap().pop_front();
+                        Ok::<_, Infallible>(respond(resp))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        IndexerStub {
+            addr,
+            responses,
+            requests,
+        }
+    }
+
+    /// Queues response to reply with to the next request.
+    pub fn respond_with(&self, resp: StubResponse) {
+        self.responses.lock().unwrap().push_back(resp);
+    }
+
+    /// Returns path and headers of received requests.
+    pub fn requests(&self) -> Vec<(String, HeaderMap)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Converts canned response to HTTP response.
+fn respond(resp: Option<StubResponse>) -> Response<Body> {
+    let stub = resp.unwrap_or_else(|| StubResponse::status(StatusCode::NOT_FOUND));
+    let mut resp = Response::new(Body::from(stub.body));
+    *resp.status_mut() = stub.status;
+    for (name, value) in stub.headers {
+        let value = HeaderValue::from_str(&value).expect("invalid header value");
+        resp.headers_mut().insert(name, value);
+    }
+
+    resp
+}
+
+// MARK: impl MockImporter
+
+impl MockImporter {
+    /// Queues result to reply with to the next import intent.
+    ///
+    /// `Ok` contains IDs of titles that failed to import.
+    pub fn respond_with(&self, result: Result<Vec<i32>, Status>) {
+        self.results.lock().unwrap().push_back(result);
+    }
+
+    /// Returns received import intents.
+    pub fn intents(&self) -> Vec<ImportIntent> {
+        self.intents.lock().unwrap().clone()
+    }
+}
+
+#[tonic::async_trait]
+impl ImportService for MockImporter {
+    async fn start_import(
+        &self,
+        request: tonic::Request<ImportIntent>,
+    ) -> Result<tonic::Response<ImportIntentResult>, Status> {
+        let intent = request.into_inner();
+        let id = intent.id.clone();
+        self.intents.lock().unwrap().push(intent);
+
+        let result = self.results.lock().unwrap().pop_front();
+        let skipped_ids = result.unwrap_or_else(|| Ok(vec![]))?;
+        Ok(tonic::Response::new(ImportIntentResult {
+            id,
+            skipped_ids,
+            imported_ids: vec![],
+        }))
+    }
+}
+
+// MARK: impl MockScraper
+
+impl MockScraper {
+    /// Queues result to reply with to the next scrape intent.
+    ///
+    /// `Ok` contains whether there's more data to scrape.
+    pub fn respond_with(&self, result: Result<bool, Status>) {
+        self.results.lock().unwrap().push_back(result);
+    }
+
+    /// Returns received scrape intents.
+    pub fn intents(&self) -> Vec<ScrapeIntent> {
+        self.intents.lock().unwrap().clone()
+    }
+}
+
+#[tonic::async_trait]
+impl ScraperService for MockScraper {
+    async fn start_scraping(
+        &self,
+        request: tonic::Request<ScrapeIntent>,
+    ) -> Result<tonic::Response<ScrapeIntentResult>, Status> {
+        let intent = request.into_inner();
+        let id = intent.id.clone();
+        self.intents.lock().unwrap().push(intent);
+
+        let result = self.results.lock().unwrap().pop_front();
+        let may_continue = result.unwrap_or(Ok(false))?;
+        Ok(tonic::Response::new(ScrapeIntentResult {
+            id,
+            may_continue,
+        }))
+    }
+}
+
+/// Starts scraping service mock on a free port and returns it's address.
+async fn start_scraper(scraper: MockScraper) -> SocketAddr {
+    let addr = free_addr();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(ScraperServiceServer::new(scraper))
+            .serve(addr),
+    );
+
+    wait_listening(addr).await;
+    addr
+}
+
+/// Returns configuration of a remote service listening on `addr`.
+fn service_config(addr: SocketAddr) -> serde_json::Value {
+    json!({
+        "url": format!("http://{}", addr),
+        "connection_timeout": 5,
+        "request_timeout": 5,
+    })
+}
+
+/// Returns address with a port that is free at the moment.
+fn free_addr() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to find free port");
+    listener.local_addr().expect("failed to find free port")
+}
+
+/// Waits until something starts listening on `addr`.
+async fn wait_listening(addr: SocketAddr) {
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_ok() {
+            return;
+        }
+
+        time::delay_for(Duration::from_millis(20)).await;
+    }
+
+    panic!("server is not listening on {}", addr);
+}
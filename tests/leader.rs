@@ -0,0 +1,117 @@
+mod common;
+
+use diesel::Connection;
+use serde_json::json;
+use tokio::{task, time};
+
+use std::time::{Duration, Instant};
+
+use satelit_scheduler::{
+    db::{lock::AdvisoryLock, ConnectionPool},
+    leader::Election,
+    settings::{Db, Leader},
+    shutdown,
+};
+
+use common::TestDb;
+
+/// How long to wait for leadership changes.
+const WAIT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_only_one_replica_holds_the_lock() {
+    let db = TestDb::new();
+
+    // advisory locks are shared by the whole database, not only by the test schema
+    let key = rand::random::<i64>();
+    let mut leader = AdvisoryLock::new(db.url(), key);
+    let mut standby = AdvisoryLock::new(db.url(), key);
+    let keepalive = Duration::from_secs(5);
+
+    task::spawn_blocking(move || {
+        assert!(leader.try_acquire(keepalive).unwrap());
+        assert!(!standby.try_acquire(keepalive).unwrap());
+
+        // renewal keeps the lock
+        assert!(leader.try_acquire(keepalive).unwrap());
+        assert!(leader.is_held().unwrap());
+
+        leader.release().unwrap();
+        assert!(!leader.is_acquired());
+        assert!(standby.try_acquire(keepalive).unwrap());
+        assert!(!leader.try_acquire(keepalive).unwrap());
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_leader_steps_down_when_lock_check_fails() {
+    let db = TestDb::new();
+    let key = rand::random::<i64>();
+    let db_settings: Db = serde_json::from_value(json!({
+        "url": db.url(),
+        "max_connections": 1,
+        "connection_timeout": 5,
+    }))
+    .expect("invalid db config");
+
+    // lease is long enough for a standby to take over if the leader waits for it to end
+    let leader_settings: Leader = serde_json::from_value(json!({
+        "election": true,
+        "lock_key": key,
+        "renew_interval": 1,
+        "lease": 60,
+    }))
+    .expect("invalid leader config");
+
+    let (election, mut leadership) = Election::new(&db_settings, &leader_settings);
+    let (stop_leader, stopped) = shutdown::channel();
+    let leader = tokio::spawn(election.run(stopped));
+    time::timeout(WAIT, leadership.acquired())
+        .await
+        .expect("replica hasn't become the leader");
+
+    // server releases the lock right away, so the leader has to notice it's gone
+    let pool = db.pool();
+    task::spawn_blocking(move || terminate_holder(&pool, key))
+        .await
+        .unwrap();
+    let terminated_at = Instant::now();
+    time::timeout(WAIT, leadership.lost())
+        .await
+        .expect("leader hasn't stepped down");
+    assert!(terminated_at.elapsed() < leader_settings.lease());
+
+    stop_leader.shutdown();
+    leader.await.unwrap();
+
+    let (election, mut other) = Election::new(&db_settings, &leader_settings);
+    let (stop_other, stopped) = shutdown::channel();
+    let other_run = tokio::spawn(election.run(stopped));
+    time::timeout(WAIT, other.acquired())
+        .await
+        .expect("another replica hasn't taken over");
+    assert!(!leadership.is_leader());
+
+    stop_other.shutdown();
+    other_run.await.unwrap();
+}
+
+/// Terminates backend of the session that holds advisory lock with the `key`.
+fn terminate_holder(pool: &ConnectionPool, key: i64) {
+    let conn = pool.get().expect("failed to get connection");
+    let query = format!(
+        "select pg_terminate_backend(pid) from pg_locks
+        where locktype = 'advisory' and granted and objsubid = 1
+            and ((classid::bigint << 32) | objid::bigint) = {}",
+        key
+    );
+
+    let terminated = conn
+        .execute(&query)
+        .expect("failed to terminate lock holder");
+    assert_eq!(terminated, 1);
+}
@@ -0,0 +1,384 @@
+mod common;
+
+use hyper::{header::IF_NONE_MATCH, StatusCode};
+use tokio::task;
+use tonic::Status;
+
+use std::time::Duration;
+
+use satelit_scheduler::{
+    db::{
+        entity::{IndexState, IntentState, Outcome, Source},
+        import::FailedImports,
+        index::IndexFiles,
+        intents::{ImportIntents, ScrapeIntents},
+        runs::PlanRuns,
+        tasks::ScrapeTasks,
+    },
+    plan::{retry, PlanError},
+    proto::uuid::Uuid,
+    settings::Recovery,
+};
+
+use common::{Harness, StubResponse};
+
+const INDEX_URL: &str = "http://storage/anidb/index-1.json.gz";
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_plan_imports_new_index_and_scrapes() {
+    let harness = Harness::new().await;
+
+    harness
+        .indexer
+        .respond_with(StubResponse::index(Source::Anidb, INDEX_URL, "hash-1"));
+    harness.importer.respond_with(Ok(vec![42, 43]));
+    harness.scraper.respond_with(Ok(true));
+
+    let more = harness.plan(Source::Anidb, 3).run().await.unwrap();
+    assert!(more);
+
+    let requests = harness.indexer.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "/anidb/latest");
+
+    let intents = harness.importer.intents();
+    assert_eq!(intents.len(), 1);
+    assert_eq!(intents[0].new_index_url, INDEX_URL);
+    assert!(intents[0].old_index_url.is_empty());
+    assert_eq!(harness.scraper.intents().len(), 1);
+
+    let pool = harness.db.pool();
+    let (index_files, failed_imports, plan_runs) = (
+        IndexFiles::new(pool.clone()),
+        FailedImports::new(pool.clone()),
+        PlanRuns::new(pool),
+    );
+    let (index, failed, runs) = task::spawn_blocking(move || {
+        (
+            index_files.latest(Source::Anidb).unwrap().unwrap(),
+            failed_imports.list(Source::Anidb).unwrap(),
+            plan_runs.list(Some(Source::Anidb), 10).unwrap(),
+        )
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(index.state, IndexState::Imported);
+    assert_eq!(index.attempts, 1);
+    assert_eq!(failed.len(), 2);
+    assert_eq!(failed[0].title_id, 42);
+    assert_eq!(failed[1].title_id, 43);
+    assert!(failed.iter().all(|f| f.attempts == 1 && !f.quarantined));
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].outcome, Outcome::Succeeded);
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_failed_import_is_recorded() {
+    let harness = Harness::new().await;
+
+    harness
+        .indexer
+        .respond_with(StubResponse::index(Source::Anidb, INDEX_URL, "hash-1"));
+    harness
+        .importer
+        .respond_with(Err(Status::unavailable("importer is down")));
+
+    let err = harness.plan(Source::Anidb, 3).run().await.unwrap_err();
+    assert!(retry::is_retryable(&err));
+    let index_id = match &err {
+        PlanError::ImportIndex(e) => e.index_id.clone(),
+        e => panic!("unexpected error: {}", e),
+    };
+
+    assert!(harness.scraper.intents().is_empty());
+
+    let pool = harness.db.pool();
+    let (index_files, plan_runs) = (IndexFiles::new(pool.clone()), PlanRuns::new(pool));
+    let (index, runs) = task::spawn_blocking(move || {
+        (
+            index_files.find(&index_id).unwrap(),
+            plan_runs.list(Some(Source::Anidb), 10).unwrap(),
+        )
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(index.state, IndexState::Failed);
+    assert_eq!(index.attempts, 1);
+    assert!(index.import_failed_at.is_some());
+    assert_eq!(runs[0].outcome, Outcome::Failed);
+    assert_eq!(
+        runs[0].error.as_deref(),
+        Some(err.report().to_string().as_str())
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_failed_titles_are_reimported_until_quarantined() {
+    let harness = Harness::new().await;
+
+    for (idx, hash) in ["hash-1", "hash-2", "hash-3"].iter().enumerate() {
+        let url = format!("http://storage/anidb/index-{}.json.gz", idx + 1);
+        harness
+            .indexer
+            .respond_with(StubResponse::index(Source::Anidb, &url, hash));
+    }
+    harness.importer.respond_with(Ok(vec![42, 43]));
+    harness.importer.respond_with(Ok(vec![42]));
+    harness.importer.respond_with(Ok(vec![]));
+
+    let plan = harness.plan(Source::Anidb, 3);
+    for _ in 0..3 {
+        plan.run().await.unwrap();
+    }
+
+    let intents = harness.importer.intents();
+    assert_eq!(intents.len(), 3);
+    assert!(intents[0].reimport_ids.is_empty());
+    assert_eq!(intents[1].reimport_ids, vec![42, 43]);
+    // 42 has failed common::MAX_TITLE_ATTEMPTS times and is quarantined
+    assert!(intents[2].reimport_ids.is_empty());
+
+    let failed_imports = FailedImports::new(harness.db.pool());
+    let failed = task::spawn_blocking(move || failed_imports.list(Source::Anidb).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].title_id, 42);
+    assert_eq!(failed[0].attempts, 2);
+    assert!(failed[0].quarantined);
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_import_result_is_applied_once() {
+    let harness = Harness::new().await;
+
+    harness
+        .indexer
+        .respond_with(StubResponse::index(Source::Anidb, INDEX_URL, "hash-1"));
+    harness.importer.respond_with(Ok(vec![42]));
+    harness.plan(Source::Anidb, 3).run().await.unwrap();
+
+    let intent_id = harness.importer.intents()[0].id.clone().unwrap();
+    let pool = harness.db.pool();
+    let (import_intents, failed_imports) =
+        (ImportIntents::new(pool.clone()), FailedImports::new(pool));
+    let (replayed, intent, failed) = task::spawn_blocking(move || {
+        (
+            import_intents.apply(&intent_id, &[], &[42], 5).unwrap(),
+            import_intents.find(&intent_id).unwrap(),
+            failed_imports.list(Source::Anidb).unwrap(),
+        )
+    })
+    .await
+    .unwrap();
+
+    assert!(replayed.is_none());
+    assert_eq!(intent.state, IntentState::Applied);
+    assert_eq!(intent.skipped_ids, vec![42]);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].attempts, 1);
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_imported_titles_are_scheduled_for_scraping() {
+    let harness = Harness::new().await;
+
+    let pool = harness.db.pool();
+    let (index_files, import_intents, tasks) = (
+        IndexFiles::new(pool.clone()),
+        ImportIntents::new(pool.clone()),
+        ScrapeTasks::new(pool),
+    );
+    let jobs = task::spawn_blocking(move || {
+        let index = index_files.queue(INDEX_URL, Source::Anidb, None).unwrap();
+        let intent_id = Uuid::new();
+        import_intents
+            .create(&intent_id, &index, None, &[])
+            .unwrap();
+        import_intents.apply(&intent_id, &[1, 2], &[3], 5).unwrap();
+
+        let (_, jobs) = tasks
+            .create(Source::Anidb, 10, Duration::from_secs(3600))
+            .unwrap();
+        jobs
+    })
+    .await
+    .unwrap();
+
+    let mut ids: Vec<_> = jobs.iter().map(|j| j.anime_id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_titles_of_stale_scraping_tasks_are_handed_out_again() {
+    let harness = Harness::new().await;
+
+    let pool = harness.db.pool();
+    let (index_files, import_intents, tasks) = (
+        IndexFiles::new(pool.clone()),
+        ImportIntents::new(pool.clone()),
+        ScrapeTasks::new(pool),
+    );
+    task::spawn_blocking(move || {
+        let index = index_files.queue(INDEX_URL, Source::Anidb, None).unwrap();
+        let intent_id = Uuid::new();
+        import_intents
+            .create(&intent_id, &index, None, &[])
+            .unwrap();
+        import_intents.apply(&intent_id, &[1, 2], &[], 5).unwrap();
+
+        let lease = Duration::from_secs(3600);
+        let (_, jobs) = tasks.create(Source::Anidb, 10, lease).unwrap();
+        assert_eq!(jobs.len(), 2);
+
+        // titles are reserved for the first task, so there's nothing to store
+        let (empty, jobs) = tasks.create(Source::Anidb, 10, lease).unwrap();
+        assert!(jobs.is_empty());
+        assert!(tasks.finish(&empty.id).unwrap().is_none());
+
+        let (_, jobs) = tasks
+            .create(Source::Anidb, 10, Duration::from_secs(0))
+            .unwrap();
+        let mut ids: Vec<_> = jobs.iter().map(|j| j.anime_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_outstanding_import_is_reissued_with_same_id() {
+    let harness = Harness::new().await;
+
+    let pool = harness.db.pool();
+    let (index_files, import_intents) = (IndexFiles::new(pool.clone()), ImportIntents::new(pool));
+    let intent_id = Uuid::new();
+    let id = intent_id.clone();
+    let index = task::spawn_blocking(move || {
+        let index = index_files.queue(INDEX_URL, Source::Anidb, None).unwrap();
+        let index = index_files.mark_importing(&index).unwrap();
+        import_intents.create(&id, &index, None, &[]).unwrap();
+        index
+    })
+    .await
+    .unwrap();
+
+    harness.importer.respond_with(Ok(vec![42]));
+    let plan = harness.plan(Source::Anidb, 3);
+    assert_eq!(plan.recover(Recovery::Reissue).await.unwrap(), 1);
+
+    let intents = harness.importer.intents();
+    assert_eq!(intents.len(), 1);
+    assert_eq!(intents[0].id, Some(intent_id.clone()));
+    assert_eq!(intents[0].new_index_url, INDEX_URL);
+
+    let pool = harness.db.pool();
+    let (index_files, import_intents) = (IndexFiles::new(pool.clone()), ImportIntents::new(pool));
+    let (index, intent) = task::spawn_blocking(move || {
+        (
+            index_files.find(&index.id).unwrap(),
+            import_intents.find(&intent_id).unwrap(),
+        )
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(index.state, IndexState::Imported);
+    assert_eq!(index.attempts, 1);
+    assert_eq!(intent.state, IntentState::Applied);
+    assert_eq!(intent.skipped_ids, vec![42]);
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_indexer_failure_stops_plan() {
+    let harness = Harness::new().await;
+
+    harness
+        .indexer
+        .respond_with(StubResponse::status(StatusCode::SERVICE_UNAVAILABLE));
+
+    let err = harness.plan(Source::Anidb, 3).run().await.unwrap_err();
+    match &err {
+        PlanError::UpdateIndex(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+
+    assert!(retry::is_retryable(&err));
+    assert!(harness.importer.intents().is_empty());
+    assert!(harness.scraper.intents().is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_not_modified_index_is_not_imported_again() {
+    let harness = Harness::new().await;
+
+    let index = StubResponse::index(Source::Anidb, INDEX_URL, "hash-1");
+    harness
+        .indexer
+        .respond_with(index.with_header("etag", "\"v1\""));
+    harness
+        .indexer
+        .respond_with(StubResponse::status(StatusCode::NOT_MODIFIED));
+
+    let plan = harness.plan(Source::Anidb, 3);
+    plan.run().await.unwrap();
+    plan.run().await.unwrap();
+
+    let requests = harness.indexer.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].1.get(IF_NONE_MATCH).is_none());
+    assert_eq!(requests[1].1.get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+    assert_eq!(harness.importer.intents().len(), 1);
+    assert_eq!(harness.scraper.intents().len(), 2);
+}
+
+#[tokio::test]
+#[ignore = "requires PG_TEST_DB_URL"]
+async fn test_scrape_intents_are_sent_to_all_scrapers() {
+    let mut harness = Harness::new().await;
+
+    let other = harness.add_scraper().await;
+    harness
+        .indexer
+        .respond_with(StubResponse::index(Source::Anidb, INDEX_URL, "hash-1"));
+    harness.scraper.respond_with(Ok(false));
+    other.respond_with(Ok(true));
+
+    let more = harness.plan(Source::Anidb, 3).run().await.unwrap();
+    assert!(more);
+
+    let (first, second) = (harness.scraper.intents(), other.intents());
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 1);
+    assert_ne!(first[0].id, second[0].id);
+
+    let scrape_intents = ScrapeIntents::new(harness.db.pool());
+    let ids = (first[0].id.clone().unwrap(), second[0].id.clone().unwrap());
+    let (first, second) = task::spawn_blocking(move || {
+        (
+            scrape_intents.find(&ids.0).unwrap(),
+            scrape_intents.find(&ids.1).unwrap(),
+        )
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(first.state, IntentState::Applied);
+    assert_eq!(second.state, IntentState::Applied);
+    assert!(first.endpoint.is_some());
+    assert_ne!(first.endpoint, second.endpoint);
+}
@@ -0,0 +1,31 @@
+#!/usr/bin/env fish
+
+set REPO_DIR (git rev-parse --show-toplevel)
+
+function gen
+  cargo run --manifest-path $REPO_DIR/tools/protogen/Cargo.toml; or exit $status
+  cargo +nightly fmt --all
+end
+
+function usage
+  echo "Generates Rust code from protobuf files in 'proto'
+Usage: proto.fish COMMAND
+
+COMMAND:
+  gen  regenerates 'src/proto' modules"
+end
+
+function main
+  set -x subcmd $argv[1]
+  switch $subcmd
+    case gen
+      gen
+    case -h --help
+      usage
+    case '*'
+      echo "Unknown command: $subcmd.\nTry --help" >&2
+      exit 1
+  end
+end
+
+main $argv
@@ -0,0 +1,23 @@
+use std::{fs, io, path::PathBuf};
+
+/// Generates gRPC services and messages in `src/proto` from protobuf files in `proto`.
+fn main() -> io::Result<()> {
+    let repo_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let proto_dir = repo_dir.join("proto");
+
+    let mut protos = vec![];
+    for entry in fs::read_dir(&proto_dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |ext| ext == "proto") {
+            protos.push(path);
+        }
+    }
+
+    protos.sort();
+    tonic_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .type_attribute("uuid.Uuid", "#[derive(Eq, Hash)]")
+        .out_dir(repo_dir.join("src/proto"))
+        .compile(&protos, &[proto_dir])
+}